@@ -2,13 +2,12 @@
 extern crate serde_derive;
 
 use atomic_counter::{AtomicCounter, RelaxedCounter};
-use futures::compat::Stream01CompatExt;
 use futures::prelude::*;
 
 use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
-use zeebest::{Client, JobResult, PanicOption, PublishMessage, WorkflowInstance, WorkflowVersion};
+use zeebest::{Client, JobDispatcher, JobResult, Message, WorkflowInstance, WorkflowVersion};
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -71,27 +70,26 @@ fn main() {
             .unwrap();
         }
         Opt::PlaceOrder { count } => {
-            for _ in 0..count {
-                futures::executor::block_on(client.create_workflow_instance(
-                    WorkflowInstance::workflow_instance_with_bpmn_process(
-                        "order-process",
-                        WorkflowVersion::Latest,
-                    ),
-                ))
-                .unwrap();
+            let workflow_instances = (0..count).map(|_| {
+                WorkflowInstance::workflow_instance_with_bpmn_process(
+                    "order-process",
+                    WorkflowVersion::Latest,
+                )
+            });
+            let created = futures::executor::block_on(
+                client.create_workflow_instances(workflow_instances, 10),
+            );
+            for created in created {
+                created.unwrap();
             }
         }
         Opt::NotifyPaymentReceived { order_id, cost } => {
             futures::executor::block_on(
-                client.publish_message(
-                    PublishMessage::new(
-                        "payment-received",
-                        order_id.to_string().as_str(),
-                        10000,
-                        "msgid",
-                    )
-                    .variables(&Payment { order_value: cost })
-                    .unwrap(),
+                client.publish(
+                    Message::new("payment-received", order_id)
+                        .time_to_live(Duration::from_secs(10))
+                        .message_id("msgid")
+                        .variables(Payment { order_value: cost }),
                 ),
             )
             .unwrap();
@@ -112,48 +110,20 @@ fn main() {
                 futures::future::ready(job_result).boxed()
             };
 
-            let initiate_payment_job = zeebest::JobWorker::new(
-                "rusty-worker".to_string(),
-                "initiate-payment".to_string(),
-                Duration::from_secs(3).as_secs() as _,
-                1,
-                PanicOption::FailJobOnPanic,
-                client.clone(),
-                initial_payment_handler,
-            );
-
-            let ship_without_insurance_job = zeebest::JobWorker::new(
-                "rusty-worker".to_string(),
-                "ship-without-insurance".to_string(),
-                Duration::from_secs(3).as_secs() as _,
-                1,
-                PanicOption::FailJobOnPanic,
-                client.clone(),
-                |_| futures::future::ready(JobResult::Complete { variables: None }).boxed(),
-            );
-
-            let ship_with_insurance_job = zeebest::JobWorker::new(
-                "rusty-worker".to_string(),
-                "ship-with-insurance".to_string(),
-                Duration::from_secs(3).as_secs() as _,
-                1,
-                PanicOption::FailJobOnPanic,
-                client.clone(),
-                |_| futures::future::ready(JobResult::Complete { variables: None }).boxed(),
-            );
-
-            let interval = tokio::timer::Interval::new_interval(Duration::from_secs(4));
-
-            let f = interval.compat().for_each(|_| {
-                let s1 = initiate_payment_job.clone().activate_and_process_jobs();
-                let s2 = ship_with_insurance_job.clone().activate_and_process_jobs();
-                let s3 = ship_without_insurance_job
-                    .clone()
-                    .activate_and_process_jobs();
-                futures::future::join3(s1, s2, s3).then(|_| futures::future::ready(()))
-            });
+            let (_worker_handle, run) = JobDispatcher::new(client)
+                .worker_name("rusty-worker")
+                .timeout(Duration::from_secs(3))
+                .max_jobs(1)
+                .route("initiate-payment", initial_payment_handler)
+                .route("ship-without-insurance", |_| {
+                    futures::future::ready(JobResult::Complete { variables: None }).boxed()
+                })
+                .route("ship-with-insurance", |_| {
+                    futures::future::ready(JobResult::Complete { variables: None }).boxed()
+                })
+                .run(Duration::from_secs(4));
 
-            futures::executor::block_on(f);
+            futures::executor::block_on(run);
         }
     }
 }
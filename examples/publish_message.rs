@@ -1,7 +1,8 @@
 #[macro_use]
 extern crate serde_derive;
 use futures::executor::block_on;
-use zeebest::{Client, PublishMessage};
+use std::time::Duration;
+use zeebest::{Client, Message};
 
 #[derive(Serialize)]
 struct Payment {
@@ -16,10 +17,11 @@ fn main() {
         total_charged: 25.95,
     };
 
-    let publish_message = PublishMessage::new("payment-confirmed", "10", 10000, "messageId")
-        .variables(&payment)
-        .unwrap();
+    let message = Message::new("payment-confirmed", 10)
+        .time_to_live(Duration::from_secs(10))
+        .message_id("messageId")
+        .variables(payment);
 
-    block_on(client.publish_message(publish_message)).unwrap();
+    block_on(client.publish(message)).unwrap();
     println!("published message");
 }
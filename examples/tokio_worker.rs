@@ -0,0 +1,22 @@
+use futures::{FutureExt, TryFutureExt};
+use std::time::Duration;
+use zeebest::{Client, JobResult, JobWorker};
+
+/// Run a job worker on a tokio runtime. zeebest does not spawn tasks or start a runtime of its
+/// own, so the worker future can be spawned next to the other tasks of a tokio service.
+fn main() {
+    let client = Client::new("127.0.0.1", 26500).unwrap();
+
+    let worker = JobWorker::builder(client, "payment-service")
+        .timeout(Duration::from_secs(10))
+        .max_jobs(4)
+        .build(|activated_job| {
+            println!("processing job {}", activated_job.key);
+            futures::future::ready(JobResult::Complete { variables: None }).boxed()
+        });
+
+    let (_handle, run) = worker.run(Duration::from_secs(1));
+
+    // tokio 0.1 runs futures 0.1, so the worker future is converted with the compat layer
+    tokio::run(run.unit_error().boxed().compat());
+}
@@ -0,0 +1,157 @@
+use crate::Error;
+use futures::{Future, FutureExt};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The gRPC status code the gateway answers with when the broker rejects a request because of
+/// backpressure.
+pub(crate) const RESOURCE_EXHAUSTED: i32 = 8;
+
+/// How a `Client` retries commands that the broker rejected because of backpressure. A rejected
+/// command was not processed, so it is safe to send it again.
+///
+/// The delay before retry `n` is drawn at random between half and all of
+/// `initial_delay * 2^(n - 1)`, capped at `max_delay`, so clients that were rejected together do
+/// not retry in lockstep. Set it with `ClientBuilder::backpressure_retry`; by default a client
+/// retries up to five times, starting at 50 milliseconds.
+#[derive(Clone)]
+pub struct BackpressureRetry {
+    max_retries: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    on_backpressure: Option<Arc<dyn Fn(u32, Duration) + Send + Sync>>,
+}
+
+impl Default for BackpressureRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+            on_backpressure: None,
+        }
+    }
+}
+
+impl BackpressureRetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never retry; backpressure fails the call right away.
+    pub fn disabled() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    /// How often a rejected command is sent again before the call fails.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The delay before the first retry.
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// The upper bound of the delay between retries.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Called with the number of the retry and the delay before it whenever a command is rejected
+    /// because of backpressure, e.g. to count rejections.
+    pub fn on_backpressure<F: Fn(u32, Duration) + Send + Sync + 'static>(
+        mut self,
+        on_backpressure: F,
+    ) -> Self {
+        self.on_backpressure = Some(Arc::new(on_backpressure));
+        self
+    }
+
+    /// The jittered delay before retry `retry`, counting from one.
+    fn delay(&self, retry: u32) -> Duration {
+        jittered_delay(self.initial_delay, self.max_delay, retry)
+    }
+
+    /// Run `call` and send it again while the broker rejects it because of backpressure.
+    pub(crate) fn retry<T, F>(
+        self,
+        call: F,
+    ) -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>
+    where
+        T: Send + 'static,
+        F: Fn() -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>> + Send + Sync + 'static,
+    {
+        retry_from(Arc::new(call), self, 1)
+    }
+}
+
+fn retry_from<T, F>(
+    call: Arc<F>,
+    backpressure_retry: BackpressureRetry,
+    retry: u32,
+) -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>
+where
+    T: Send + 'static,
+    F: Fn() -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>> + Send + Sync + 'static,
+{
+    (*call)()
+        .then(move |result| match result {
+            Err(ref e) if e.is_backpressure() && retry <= backpressure_retry.max_retries => {
+                let delay = backpressure_retry.delay(retry);
+                if let Some(on_backpressure) = &backpressure_retry.on_backpressure {
+                    on_backpressure(retry, delay);
+                }
+                futures_timer::Delay::new(delay)
+                    .then(move |_| retry_from(call, backpressure_retry, retry + 1))
+                    .left_future()
+            }
+            result => futures::future::ready(result).right_future(),
+        })
+        .boxed()
+}
+
+/// A delay drawn at random between half and all of `initial_delay * 2^(retry - 1)`, capped at
+/// `max_delay`, for retry `retry` counting from one.
+pub(crate) fn jittered_delay(initial_delay: Duration, max_delay: Duration, retry: u32) -> Duration {
+    let factor = 1u32 << (retry - 1).min(16);
+    let delay = initial_delay
+        .checked_mul(factor)
+        .map_or(max_delay, |delay| std::cmp::min(delay, max_delay));
+    let half = delay / 2;
+    let jitter_millis = half.as_millis() as u64 + 1;
+    half + Duration::from_millis(random() % jitter_millis)
+}
+
+/// A random number for jitter. Every `RandomState` is seeded with fresh random keys, which is
+/// good enough to spread retries without depending on a random number generator.
+pub(crate) fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BackpressureRetry;
+    use std::time::Duration;
+
+    #[test]
+    fn delays_grow_with_jitter_up_to_the_max() {
+        let backpressure_retry = BackpressureRetry::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300));
+        for _ in 0..20 {
+            let first = backpressure_retry.delay(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let second = backpressure_retry.delay(2);
+            assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+            let capped = backpressure_retry.delay(10);
+            assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use futures::executor::block_on;
+use futures::prelude::*;
+use structopt::StructOpt;
+use zeebest::{
+    ActivateJobs, Client, ClientBuilder, DeployRequest, Error, PublishMessage, WorkflowInstance,
+    WorkflowVersion,
+};
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "zeebest",
+    about = "A command line client for the zeebe gateway."
+)]
+struct Opt {
+    #[structopt(
+        long = "address",
+        help = "The address of the gateway, as host:port. Without it the client is configured from the ZEEBE_* environment variables, like zbctl."
+    )]
+    address: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(
+        name = "deploy",
+        about = "Deploy workflow files in a single deployment. Directories deploy every workflow file they contain."
+    )]
+    Deploy { paths: Vec<String> },
+    #[structopt(
+        name = "create-instance",
+        about = "Create a workflow instance of the latest or a given version."
+    )]
+    CreateInstance {
+        bpmn_process_id: String,
+        #[structopt(long = "version")]
+        version: Option<i32>,
+        #[structopt(long = "variables", help = "The variables as a JSON object.")]
+        variables: Option<String>,
+    },
+    #[structopt(name = "publish-message", about = "Publish a message.")]
+    PublishMessage {
+        name: String,
+        #[structopt(long = "correlation-key")]
+        correlation_key: String,
+        #[structopt(
+            long = "ttl",
+            default_value = "0",
+            help = "The time to live in milliseconds."
+        )]
+        time_to_live: i64,
+        #[structopt(long = "message-id", default_value = "")]
+        message_id: String,
+        #[structopt(long = "variables", help = "The variables as a JSON object.")]
+        variables: Option<String>,
+    },
+    #[structopt(name = "cancel-instance", about = "Cancel a workflow instance.")]
+    CancelInstance { workflow_instance_key: i64 },
+    #[structopt(name = "topology", about = "Print the topology of the cluster.")]
+    Topology,
+    #[structopt(
+        name = "activate",
+        about = "Activate jobs and print them. The jobs stay locked until the timeout passes."
+    )]
+    Activate {
+        job_type: String,
+        #[structopt(long = "max-jobs", default_value = "1")]
+        max_jobs: i32,
+        #[structopt(
+            long = "timeout",
+            default_value = "300000",
+            help = "The job timeout in milliseconds."
+        )]
+        timeout: i64,
+        #[structopt(long = "worker", default_value = "zeebest")]
+        worker: String,
+    },
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let client = match &opt.address {
+        Some(address) => ClientBuilder::from_address(address)
+            .map_err(|e| {
+                eprintln!("{}", e);
+                std::process::exit(2)
+            })
+            .and_then(ClientBuilder::build),
+        None => Client::from_env(),
+    }
+    .expect("Could not connect to the gateway.");
+    if let Err(e) = run(&client, opt.command) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn variables(variables: Option<String>) -> Result<Option<serde_json::Value>, Error> {
+    variables
+        .map(|variables| serde_json::from_str(&variables).map_err(Error::JsonDeserializationError))
+        .transpose()
+}
+
+fn run(client: &Client, command: Command) -> Result<(), Error> {
+    match command {
+        Command::Deploy { paths } => {
+            let mut deploy_request = DeployRequest::new();
+            for path in paths {
+                deploy_request = if std::path::Path::new(&path).is_dir() {
+                    deploy_request.dir(path)?
+                } else {
+                    deploy_request.path(path)?
+                };
+            }
+            println!("{:#?}", block_on(client.deploy(deploy_request))?);
+        }
+        Command::CreateInstance {
+            bpmn_process_id,
+            version,
+            variables: instance_variables,
+        } => {
+            let version = version.map_or(WorkflowVersion::Latest, WorkflowVersion::Version);
+            let mut workflow_instance =
+                WorkflowInstance::workflow_instance_with_bpmn_process(bpmn_process_id, version);
+            if let Some(instance_variables) = variables(instance_variables)? {
+                workflow_instance = workflow_instance.variables(&instance_variables)?;
+            }
+            println!(
+                "{:#?}",
+                block_on(client.create_workflow_instance(workflow_instance))?
+            );
+        }
+        Command::PublishMessage {
+            name,
+            correlation_key,
+            time_to_live,
+            message_id,
+            variables: message_variables,
+        } => {
+            let mut publish_message =
+                PublishMessage::new(name, correlation_key, time_to_live, message_id);
+            if let Some(message_variables) = variables(message_variables)? {
+                publish_message = publish_message.variables(&message_variables)?;
+            }
+            block_on(client.publish_message(publish_message))?;
+        }
+        Command::CancelInstance {
+            workflow_instance_key,
+        } => block_on(client.cancel_workflow_instance(workflow_instance_key))?,
+        Command::Topology => println!("{:#?}", block_on(client.topology())?),
+        Command::Activate {
+            job_type,
+            max_jobs,
+            timeout,
+            worker,
+        } => {
+            let activate_jobs = ActivateJobs::new(worker, job_type, timeout, max_jobs);
+            let batches: Vec<_> = block_on(client.activate_jobs(activate_jobs).collect());
+            for batch in batches {
+                for activated_job in batch?.activated_jobs {
+                    println!("{:#?}", activated_job);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,12 +1,22 @@
 use crate::gateway;
 use crate::gateway_grpc::*;
+use crate::instrument;
 use futures::compat::{Future01CompatExt, Stream01CompatExt};
-use futures::future::{Future, TryFutureExt};
-use futures::stream::{Stream, TryStreamExt};
-use grpc::ClientStubExt;
+use futures::future::{Either, Future, FutureExt, TryFutureExt};
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::backpressure::RESOURCE_EXHAUSTED;
 use crate::gateway::TopologyResponse;
+use crate::reconnect::{ConnectionPool, Generation};
+use crate::{
+    BackpressureRetry, ClientBuilder, CredentialsProvider, Interceptor, RequestMetadata,
+    RetryPolicy,
+};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 #[derive(Debug, Fail)]
@@ -29,13 +39,182 @@ pub enum Error {
     PublishMessageError(grpc::Error),
     #[fail(display = "Fail Job Error. {:?}", _0)]
     FailJobError(grpc::Error),
-    #[cfg(feature = "timer")]
-    #[fail(display = "Interval Error. {:?}", _0)]
-    IntervalError(tokio::timer::Error),
+    #[fail(display = "Cancel Workflow Instance Error. {:?}", _0)]
+    CancelWorkflowInstanceError(grpc::Error),
+    #[fail(display = "Set Variables Error. {:?}", _0)]
+    SetVariablesError(grpc::Error),
+    #[fail(display = "Update Job Retries Error. {:?}", _0)]
+    UpdateJobRetriesError(grpc::Error),
+    #[fail(display = "Resolve Incident Error. {:?}", _0)]
+    ResolveIncidentError(grpc::Error),
+    #[fail(display = "Resource Error. {:?}: {}", _0, _1)]
+    ResourceError(PathBuf, std::io::Error),
     #[fail(display = "Job Error: {}", _0)]
     JobError(String),
     #[fail(display = "Json Payload Serialization Error. {:?}", _0)]
     JsonError(serde_json::error::Error),
+    #[fail(display = "Json Payload Deserialization Error. {}", _0)]
+    JsonDeserializationError(serde_json::error::Error),
+    #[fail(display = "Variables must be a JSON object, got {}", _0)]
+    VariablesNotAnObject(String),
+    #[fail(display = "Credentials Error: {}", _0)]
+    CredentialsError(String),
+    #[fail(display = "Configuration Error: {}", _0)]
+    ConfigurationError(String),
+    #[fail(display = "Unknown Tenant: {}", _0)]
+    UnknownTenant(String),
+    #[fail(display = "Deadline Exceeded.")]
+    DeadlineExceeded,
+    #[cfg(feature = "tls")]
+    #[fail(display = "TLS Error. {:?}", _0)]
+    TlsError(tls_api::Error),
+}
+
+/// The broad category of an `Error`, for deciding how to handle it without matching every
+/// variant. The status of a failed gateway call stays available through `Error::grpc_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The connection to the gateway failed.
+    Connection,
+    /// The broker rejected the request because of backpressure; the request was not processed.
+    Backpressure,
+    /// The gateway or the broker is not available right now, e.g. during a leader election.
+    Unavailable,
+    /// The referenced resource, e.g. a job or a workflow instance, does not exist.
+    NotFound,
+    /// The request was invalid, e.g. its variables are not a JSON object.
+    InvalidArgument,
+    /// The resource already exists, e.g. a message with the same id was published.
+    AlreadyExists,
+    /// The request conflicts with the state of the resource, e.g. the job has no retries.
+    FailedPrecondition,
+    /// The call did not finish before its deadline.
+    DeadlineExceeded,
+    /// Serializing or deserializing a payload failed.
+    Serialization,
+    /// The credentials for the call could not be obtained.
+    Credentials,
+    /// Any other error.
+    Other,
+}
+
+const INVALID_ARGUMENT: i32 = 3;
+const DEADLINE_EXCEEDED: i32 = 4;
+const NOT_FOUND: i32 = 5;
+const ALREADY_EXISTS: i32 = 6;
+const FAILED_PRECONDITION: i32 = 9;
+const UNAVAILABLE: i32 = 14;
+
+impl Error {
+    /// The category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::JsonError(_) | Error::JsonDeserializationError(_) => ErrorKind::Serialization,
+            Error::VariablesNotAnObject(_) => ErrorKind::InvalidArgument,
+            Error::CredentialsError(_) => ErrorKind::Credentials,
+            Error::ConfigurationError(_) => ErrorKind::InvalidArgument,
+            Error::UnknownTenant(_) => ErrorKind::InvalidArgument,
+            Error::DeadlineExceeded => ErrorKind::DeadlineExceeded,
+            _ => match self.grpc_error() {
+                Some(grpc::Error::Io(_)) | Some(grpc::Error::Http(_)) => ErrorKind::Connection,
+                Some(grpc::Error::GrpcMessage(message)) => match message.grpc_status {
+                    RESOURCE_EXHAUSTED => ErrorKind::Backpressure,
+                    UNAVAILABLE => ErrorKind::Unavailable,
+                    NOT_FOUND => ErrorKind::NotFound,
+                    INVALID_ARGUMENT => ErrorKind::InvalidArgument,
+                    ALREADY_EXISTS => ErrorKind::AlreadyExists,
+                    FAILED_PRECONDITION => ErrorKind::FailedPrecondition,
+                    DEADLINE_EXCEEDED => ErrorKind::DeadlineExceeded,
+                    _ => ErrorKind::Other,
+                },
+                _ => ErrorKind::Other,
+            },
+        }
+    }
+
+    /// True if the broker rejected the request because of backpressure. The request was not
+    /// processed and may be sent again later.
+    pub fn is_backpressure(&self) -> bool {
+        self.kind() == ErrorKind::Backpressure
+    }
+
+    /// True if the call failed because the connection to the gateway failed. The client opens a
+    /// new connection for the next call, see `ReconnectPolicy`.
+    pub fn is_connection_error(&self) -> bool {
+        self.kind() == ErrorKind::Connection
+    }
+
+    /// The gRPC status code and message the gateway answered a failed call with.
+    pub fn grpc_status(&self) -> Option<(i32, &str)> {
+        match self.grpc_error() {
+            Some(grpc::Error::GrpcMessage(message)) => {
+                Some((message.grpc_status, message.grpc_message.as_str()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The error of the gateway call, if this error was returned by the gateway.
+    pub fn grpc_error(&self) -> Option<&grpc::Error> {
+        match self {
+            Error::GatewayError(e)
+            | Error::TopologyError(e)
+            | Error::ListWorkflowsError(e)
+            | Error::DeployWorkflowError(e)
+            | Error::CreateWorkflowInstanceError(e)
+            | Error::ActivateJobError(e)
+            | Error::CompleteJobError(e)
+            | Error::PublishMessageError(e)
+            | Error::FailJobError(e)
+            | Error::CancelWorkflowInstanceError(e)
+            | Error::SetVariablesError(e)
+            | Error::UpdateJobRetriesError(e)
+            | Error::ResolveIncidentError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Serialize `variables` into a JSON document, checking that it is a JSON object as zeebe
+/// requires for all variable payloads.
+pub(crate) fn serialize_variables<S: Serialize>(variables: &S) -> Result<String, Error> {
+    let value = serde_json::to_value(variables).map_err(|e| Error::JsonError(e))?;
+    check_object(&value).map(|_| value.to_string())
+}
+
+/// Check that a JSON document of variables is a JSON object, without changing it. An empty
+/// document is fine, the gateway reads it as no variables.
+pub(crate) fn check_variables(variables: &str) -> Result<(), Error> {
+    if variables.trim().is_empty() {
+        return Ok(());
+    }
+    serde_json::from_str::<serde_json::Value>(variables)
+        .map_err(|e| Error::JsonDeserializationError(e))
+        .and_then(|value| check_object(&value))
+}
+
+fn check_object(value: &serde_json::Value) -> Result<(), Error> {
+    let kind = match value {
+        serde_json::Value::Object(_) => return Ok(()),
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+    };
+    Err(Error::VariablesNotAnObject(kind.to_string()))
+}
+
+/// Parse a JSON document of variables, checking that it is a JSON object.
+pub(crate) fn parse_variables(variables: &str) -> Result<String, Error> {
+    serde_json::from_str::<serde_json::Value>(variables)
+        .map_err(|e| Error::JsonDeserializationError(e))
+        .and_then(|value| serialize_variables(&value))
+}
+
+/// The milliseconds of `duration`, capped at `i64::MAX`.
+pub(crate) fn saturating_millis(duration: Duration) -> i64 {
+    std::cmp::min(duration.as_millis(), i64::max_value() as u128) as i64
 }
 
 /// Strongly type the version. `WorkflowVersion::Latest` is translated to `-1`.
@@ -54,28 +233,259 @@ impl Into<i32> for WorkflowVersion {
 }
 
 /// The primary type for interacting with zeebe.
+///
+/// Cloning a client is cheap and never opens a connection: every clone shares the connections,
+/// credentials and settings of the client it was cloned from, so any number of workers and tasks
+/// can multiplex their calls over one HTTP/2 connection. Clients that send a very large number
+/// of calls can spread them over a small pool of connections with `ClientBuilder::connections`.
 #[derive(Clone)]
 pub struct Client {
     pub gateway_client: Arc<dyn Gateway + Send + Sync>,
+    pub(crate) credentials: Option<Arc<dyn CredentialsProvider + Send + Sync>>,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) backpressure_retry: BackpressureRetry,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) connections: Option<Arc<ConnectionPool>>,
+    pub(crate) interceptors: Vec<Arc<dyn Interceptor + Send + Sync>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Option<crate::Metrics>,
 }
 
 impl Client {
     /// Construct a new `Client` that connects to a broker with `host` and `port`.
     pub fn new(host: &str, port: u16) -> Result<Self, Error> {
-        GatewayClient::new_plain(host, port, Default::default())
-            .map_err(|e| Error::GatewayError(e))
-            .map(Arc::new)
-            .map(|gateway_client| Client { gateway_client })
+        Client::builder(host, port).build()
+    }
+
+    /// Construct a new `Client` from the same environment variables as `zbctl`, see
+    /// `ClientBuilder::from_env`.
+    pub fn from_env() -> Result<Self, Error> {
+        ClientBuilder::from_env()?.build()
+    }
+
+    /// Start configuring a `Client` that connects to a broker with `host` and `port`.
+    pub fn builder<S: Into<String>>(host: S, port: u16) -> ClientBuilder {
+        ClientBuilder::new(host, port)
+    }
+
+    pub(crate) fn from_gateway_client(gateway_client: Arc<dyn Gateway + Send + Sync>) -> Self {
+        Client {
+            gateway_client,
+            credentials: None,
+            request_timeout: None,
+            backpressure_retry: BackpressureRetry::disabled(),
+            retry_policy: None,
+            connections: None,
+            interceptors: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Attach the credentials of `credentials_provider` to every call made by this client.
+    pub fn with_credentials<C: CredentialsProvider + Send + Sync + 'static>(
+        mut self,
+        credentials_provider: C,
+    ) -> Self {
+        self.credentials = Some(Arc::new(credentials_provider));
+        self
+    }
+
+    /// Run `interceptor` on the metadata of every call made by this client, after the interceptors
+    /// that were added before.
+    pub fn with_interceptor<I: Interceptor + Send + Sync + 'static>(
+        mut self,
+        interceptor: I,
+    ) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// A client that bounds each unary call with `request_timeout` instead of the deadline it was
+    /// built with. The clone shares the connection, so it is cheap to override the deadline of a
+    /// single call:
+    ///
+    /// ```rust,ignore
+    /// client.with_request_timeout(Duration::from_secs(2)).publish_message(message)
+    /// ```
+    ///
+    /// A call that misses its deadline fails with `Error::DeadlineExceeded`. Dropping the future
+    /// of a call cancels it.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// A client whose unary calls have no deadline.
+    pub fn without_request_timeout(mut self) -> Self {
+        self.request_timeout = None;
+        self
+    }
+
+    /// The request options for a single gateway call.
+    fn request_options(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<grpc::RequestOptions, Error>> + Send>> {
+        let request_options = self.authorized_request_options();
+        if self.interceptors.is_empty() {
+            return request_options;
+        }
+        let interceptors = self.interceptors.clone();
+        request_options
+            .map_ok(move |mut request_options| {
+                for interceptor in &interceptors {
+                    interceptor.intercept(&mut RequestMetadata::new(&mut request_options.metadata));
+                }
+                request_options
+            })
+            .boxed()
+    }
+
+    /// The request options with the credentials of the client attached.
+    fn authorized_request_options(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<grpc::RequestOptions, Error>> + Send>> {
+        match &self.credentials {
+            None => futures::future::ok(Default::default()).boxed(),
+            Some(credentials) => credentials
+                .authorization()
+                .map_ok(|authorization| {
+                    let mut request_options = grpc::RequestOptions::default();
+                    request_options.metadata.add(
+                        grpc::MetadataKey::from("authorization"),
+                        bytes::Bytes::from(authorization),
+                    );
+                    request_options
+                })
+                .boxed(),
+        }
+    }
+
+    /// The gateway client for the next call, with the generation of its connection.
+    fn connected_gateway_client(&self) -> (Generation, Arc<dyn Gateway + Send + Sync>) {
+        match &self.connections {
+            Some(connections) => connections.gateway_client(),
+            None => ((0, 0), self.gateway_client.clone()),
+        }
+    }
+
+    /// Tell the connection and the metrics how a call on the connection of `generation` went.
+    fn report_result<T>(&self, generation: Generation, result: &Result<T, Error>) {
+        if let Some(connections) = &self.connections {
+            connections.report(generation, result.as_ref().map(|_| ()));
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let (Some(metrics), Err(e)) = (&self.metrics, result) {
+                metrics.grpc_error(e);
+            }
+        }
+    }
+
+    /// Perform a unary gateway call. Calls rejected because of backpressure are retried according
+    /// to the `BackpressureRetry` of the client; the deadline bounds all attempts together.
+    fn unary_call<Req, Resp>(
+        &self,
+        request: Req,
+        map_err: fn(grpc::Error) -> Error,
+        rpc: fn(
+            &(dyn Gateway + Send + Sync),
+            grpc::RequestOptions,
+            Req,
+        ) -> grpc::SingleResponse<Resp>,
+    ) -> Pin<Box<dyn Future<Output = Result<Resp, Error>> + Send>>
+    where
+        Req: Clone + Send + Sync + 'static,
+        Resp: Send + 'static,
+    {
+        let client = self.clone();
+        let call = self.backpressure_retry.clone().retry(move || {
+            let (generation, gateway_client) = client.connected_gateway_client();
+            let request = request.clone();
+            let reporter = client.clone();
+            client
+                .request_options()
+                .and_then(move |request_options| {
+                    rpc(gateway_client.as_ref(), request_options, request)
+                        .drop_metadata()
+                        .compat()
+                        .map_err(map_err)
+                })
+                .inspect(move |result| reporter.report_result(generation, result))
+                .boxed()
+        });
+        let call = match self.request_timeout {
+            Some(request_timeout) => with_deadline(call, request_timeout).boxed(),
+            None => call,
+        };
+        instrument::rpc_future::<Req, _>(call)
+    }
+
+    /// Run an idempotent call with the retry policy, if there is one.
+    fn retry_idempotent<T, F>(
+        &self,
+        call: F,
+    ) -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>
+    where
+        T: Send + 'static,
+        F: Fn() -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>> + Send + Sync + 'static,
+    {
+        match &self.retry_policy {
+            Some(retry_policy) => retry_policy.clone().retry(call),
+            None => call(),
+        }
+    }
+
+    /// Perform a server streaming gateway call.
+    fn streaming_call<Req, Resp>(
+        &self,
+        request: Req,
+        map_err: fn(grpc::Error) -> Error,
+        rpc: fn(
+            &(dyn Gateway + Send + Sync),
+            grpc::RequestOptions,
+            Req,
+        ) -> grpc::StreamingResponse<Resp>,
+    ) -> Pin<Box<dyn Stream<Item = Result<Resp, Error>> + Send>>
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        let (generation, gateway_client) = self.connected_gateway_client();
+        let reporter = self.clone();
+        let stream = self
+            .request_options()
+            .map_ok(move |request_options| {
+                rpc(gateway_client.as_ref(), request_options, request)
+                    .drop_metadata()
+                    .compat()
+                    .map_err(map_err)
+            })
+            .try_flatten_stream()
+            .inspect(move |result| reporter.report_result(generation, result))
+            .boxed();
+        instrument::rpc_stream::<Req, _>(stream)
     }
 
     /// Get the topology. The returned struct is similar to what is printed when running `zbctl status`.
-    pub fn topology(&self) -> impl Future<Output = Result<Topology, Error>> {
-        self.gateway_client
-            .topology(Default::default(), Default::default())
-            .drop_metadata()
-            .compat()
-            .map_ok(|tr| Topology::new(tr))
-            .map_err(|e| Error::TopologyError(e))
+    pub fn topology(&self) -> impl Future<Output = Result<Topology, Error>> + Send {
+        let client = self.clone();
+        self.retry_idempotent(move || {
+            client.unary_call(
+                gateway::TopologyRequest::default(),
+                Error::TopologyError,
+                |g, o, r| g.topology(o, r),
+            )
+        })
+        .map_ok(|tr| Topology::new(tr))
+    }
+
+    /// Check whether the cluster is ready to serve requests, i.e. the gateway answers and every
+    /// partition has a leader. Useful as a readiness probe before starting workers; a failed
+    /// topology request counts as unhealthy.
+    pub fn healthy(&self) -> impl Future<Output = bool> + Send {
+        self.topology()
+            .map(|topology| topology.map(|t| t.is_healthy()).unwrap_or(false))
     }
 
     /// deploy a single bpmn workflow
@@ -83,35 +493,77 @@ impl Client {
         &self,
         workflow_name: S,
         workflow_definition: Vec<u8>,
-    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> {
-        // construct request
-        let mut workflow_request_object = gateway::WorkflowRequestObject::default();
-        workflow_request_object.set_name(workflow_name.into());
-        workflow_request_object.set_definition(workflow_definition);
-        workflow_request_object.set_field_type(gateway::WorkflowRequestObject_ResourceType::BPMN);
-        let mut deploy_workflow_request = gateway::DeployWorkflowRequest::default();
-        deploy_workflow_request
-            .set_workflows(protobuf::RepeatedField::from(vec![workflow_request_object]));
-        // deploy the bpmn workflow
-        self.gateway_client
-            .deploy_workflow(Default::default(), deploy_workflow_request)
-            .drop_metadata()
-            .compat()
-            .map_err(|e| Error::DeployWorkflowError(e))
-            .map_ok(|dwr| DeployedWorkflows::new(dwr))
+    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> + Send {
+        self.deploy(DeployRequest::new().bpmn(workflow_name, workflow_definition))
+    }
+
+    /// deploy all resources of a `DeployRequest` in a single deployment
+    pub fn deploy(
+        &self,
+        deploy_request: DeployRequest,
+    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> + Send {
+        let client = self.clone();
+        let request: gateway::DeployWorkflowRequest = deploy_request.into();
+        self.retry_idempotent(move || {
+            client.unary_call(request.clone(), Error::DeployWorkflowError, |g, o, r| {
+                g.deploy_workflow(o, r)
+            })
+        })
+        .map_ok(|dwr| DeployedWorkflows::new(dwr))
+    }
+
+    /// deploy a single resource file, named after the file, see `DeployRequest::path`
+    pub fn deploy_from_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> + Send {
+        match DeployRequest::new().path(path) {
+            Ok(deploy_request) => self.deploy(deploy_request).left_future(),
+            Err(e) => futures::future::err(e).right_future(),
+        }
+    }
+
+    /// deploy every workflow file of a directory in a single deployment, see `DeployRequest::dir`
+    pub fn deploy_from_dir<P: AsRef<Path>>(
+        &self,
+        dir: P,
+    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> + Send {
+        match DeployRequest::new().dir(dir) {
+            Ok(deploy_request) => self.deploy(deploy_request).left_future(),
+            Err(e) => futures::future::err(e).right_future(),
+        }
     }
 
     /// create a workflow instance with a payload
     pub fn create_workflow_instance(
         &self,
         workflow_instance: WorkflowInstance,
-    ) -> impl Future<Output = Result<CreatedWorkflowInstance, Error>> {
-        self.gateway_client
-            .create_workflow_instance(Default::default(), workflow_instance.into())
-            .drop_metadata()
-            .compat()
-            .map_err(|e| Error::CreateWorkflowInstanceError(e))
-            .map_ok(|cwr| CreatedWorkflowInstance::new(cwr))
+    ) -> impl Future<Output = Result<CreatedWorkflowInstance, Error>> + Send {
+        self.unary_call(
+            workflow_instance.into(),
+            Error::CreateWorkflowInstanceError,
+            |g, o, r| g.create_workflow_instance(o, r),
+        )
+        .map_ok(|cwr| CreatedWorkflowInstance::new(cwr))
+    }
+
+    /// create many workflow instances with at most `concurrency` requests in flight. Resolves to
+    /// the result of every instance, in the order of `workflow_instances`, once all are done; a
+    /// failed instance does not stop the others.
+    pub fn create_workflow_instances<I>(
+        &self,
+        workflow_instances: I,
+        concurrency: usize,
+    ) -> impl Future<Output = Vec<Result<CreatedWorkflowInstance, Error>>> + Send
+    where
+        I: IntoIterator<Item = WorkflowInstance>,
+        I::IntoIter: Send + 'static,
+    {
+        let client = self.clone();
+        futures::stream::iter(workflow_instances)
+            .map(move |workflow_instance| client.create_workflow_instance(workflow_instance))
+            .buffered(std::cmp::max(concurrency, 1))
+            .collect()
     }
 
     /// activate jobs
@@ -119,25 +571,38 @@ impl Client {
         &self,
         jobs_config: ActivateJobs,
     ) -> impl Stream<Item = Result<ActivatedJobs, Error>> + Send {
-        self.gateway_client
-            .activate_jobs(Default::default(), jobs_config.into())
-            .drop_metadata()
-            .compat()
-            .map_err(|e| Error::ActivateJobError(e))
-            .map_ok(|ajr| ActivatedJobs::new(ajr))
+        self.streaming_call(jobs_config.into(), Error::ActivateJobError, |g, o, r| {
+            g.activate_jobs(o, r)
+        })
+        .map_ok(|ajr| ActivatedJobs::new(ajr))
     }
 
-    /// complete a job
+    /// activate jobs and yield them one at a time instead of in the batches the gateway sends,
+    /// for building custom processing pipelines on top of the raw activation stream
+    pub fn activated_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> impl Stream<Item = Result<ActivatedJob, Error>> + Send {
+        flatten_batches(self.activate_jobs(jobs_config))
+    }
+
+    /// complete a job. Fails with `Error::VariablesNotAnObject` if the variables are not a JSON
+    /// object.
     pub fn complete_job(
         &self,
         complete_job: CompleteJob,
     ) -> impl Future<Output = Result<(), Error>> + Send {
-        self.gateway_client
-            .complete_job(Default::default(), complete_job.into())
-            .drop_metadata()
-            .compat()
-            .map_err(|e| Error::CompleteJobError(e))
-            .map_ok(|_| ())
+        // zeebe only accepts a JSON object, fail before sending anything else
+        if let Some(variables) = &complete_job.variables {
+            if let Err(e) = check_variables(variables) {
+                return futures::future::err(e).right_future();
+            }
+        }
+        self.unary_call(complete_job.into(), Error::CompleteJobError, |g, o, r| {
+            g.complete_job(o, r)
+        })
+        .map_ok(|_| ())
+        .left_future()
     }
 
     /// fail a job
@@ -147,30 +612,452 @@ impl Client {
         retries: i32,
         error_message: String,
     ) -> impl Future<Output = Result<(), Error>> + Send {
-        let request_options = Default::default();
         let mut request = gateway::FailJobRequest::default();
         request.set_jobKey(job_key);
         request.set_retries(retries);
         request.set_errorMessage(error_message);
-        self.gateway_client
-            .fail_job(request_options, request)
-            .drop_metadata()
-            .compat()
+        self.unary_call(request, Error::FailJobError, |g, o, r| g.fail_job(o, r))
             .map_ok(|_| ())
-            .map_err(|e| Error::FailJobError(e))
     }
 
     /// Publish a message
     pub fn publish_message(
         &self,
         publish_message: PublishMessage,
-    ) -> impl Future<Output = Result<(), Error>> {
-        self.gateway_client
-            .publish_message(Default::default(), publish_message.into())
-            .drop_metadata()
-            .compat()
-            .map_err(|e| Error::PublishMessageError(e))
-            .map_ok(|_| ())
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let request: gateway::PublishMessageRequest = publish_message.into();
+        let publish = |client: &Client, request| {
+            client.unary_call(request, Error::PublishMessageError, |g, o, r| {
+                g.publish_message(o, r)
+            })
+        };
+        // without a message id a retried message could be correlated twice
+        let call = if request.messageId.is_empty() {
+            publish(self, request)
+        } else {
+            let client = self.clone();
+            self.retry_idempotent(move || publish(&client, request.clone()))
+        };
+        call.map_ok(|_| ())
+    }
+
+    /// Publish a typed `Message`. Like `publish_message` it resolves to `()`, since the gateway of
+    /// this zeebe version does not return the key of a published message.
+    pub fn publish<T: Serialize>(
+        &self,
+        message: Message<T>,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        match message.into_publish_message() {
+            Ok(publish_message) => self.publish_message(publish_message).left_future(),
+            Err(e) => futures::future::err(e).right_future(),
+        }
+    }
+
+    /// Cancel a running workflow instance.
+    pub fn cancel_workflow_instance(
+        &self,
+        workflow_instance_key: i64,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let mut request = gateway::CancelWorkflowInstanceRequest::default();
+        request.set_workflowInstanceKey(workflow_instance_key);
+        self.unary_call(request, Error::CancelWorkflowInstanceError, |g, o, r| {
+            g.cancel_workflow_instance(o, r)
+        })
+        .map_ok(|_| ())
+    }
+
+    /// Set variables on the scope of an element instance, e.g. a workflow instance or a service
+    /// task. With `local` the variables are only set on that scope and not propagated to its
+    /// parents. The variables must serialize to a JSON object.
+    pub fn set_variables<S: Serialize>(
+        &self,
+        element_instance_key: i64,
+        variables: &S,
+        local: bool,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        match serialize_variables(variables) {
+            Ok(variables) => self
+                .set_variables_request(element_instance_key, variables, local)
+                .left_future(),
+            Err(e) => futures::future::err(e).right_future(),
+        }
+    }
+
+    /// Like `set_variables`, but takes the variables as a JSON document.
+    pub fn set_variables_json<S: AsRef<str>>(
+        &self,
+        element_instance_key: i64,
+        variables: S,
+        local: bool,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        match parse_variables(variables.as_ref()) {
+            Ok(variables) => self
+                .set_variables_request(element_instance_key, variables, local)
+                .left_future(),
+            Err(e) => futures::future::err(e).right_future(),
+        }
+    }
+
+    fn set_variables_request(
+        &self,
+        element_instance_key: i64,
+        variables: String,
+        local: bool,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let mut request = gateway::SetVariablesRequest::default();
+        request.set_elementInstanceKey(element_instance_key);
+        request.set_variables(variables);
+        request.set_local(local);
+        self.unary_call(request, Error::SetVariablesError, |g, o, r| {
+            g.set_variables(o, r)
+        })
+        .map_ok(|_| ())
+    }
+
+    /// Set the retries of a job, e.g. to retry a job that raised an incident. `retries` must be
+    /// positive.
+    pub fn update_job_retries(
+        &self,
+        job_key: i64,
+        retries: i32,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let mut request = gateway::UpdateJobRetriesRequest::default();
+        request.set_jobKey(job_key);
+        request.set_retries(retries);
+        self.unary_call(request, Error::UpdateJobRetriesError, |g, o, r| {
+            g.update_job_retries(o, r)
+        })
+        .map_ok(|_| ())
+    }
+
+    /// Resolve an incident. If the incident was raised by a job, update the job retries first.
+    pub fn resolve_incident(
+        &self,
+        incident_key: i64,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let mut request = gateway::ResolveIncidentRequest::default();
+        request.set_incidentKey(incident_key);
+        self.unary_call(request, Error::ResolveIncidentError, |g, o, r| {
+            g.resolve_incident(o, r)
+        })
+        .map_ok(|_| ())
+    }
+}
+
+/// Fail with `Error::DeadlineExceeded` if `future` does not resolve within `deadline`.
+fn with_deadline<T: Send + 'static>(
+    future: Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>,
+    deadline: Duration,
+) -> impl Future<Output = Result<T, Error>> + Send {
+    futures::future::select(future, futures_timer::Delay::new(deadline)).then(|either| match either
+    {
+        Either::Left((result, _)) => futures::future::ready(result).left_future(),
+        Either::Right((Ok(()), _)) => futures::future::err(Error::DeadlineExceeded).left_future(),
+        // the timer is gone, so the call can only end on its own
+        Either::Right((Err(_), future)) => future.right_future(),
+    })
+}
+
+/// The gateway operations of a zeebe client. `Client` implements this trait; downstream crates
+/// can write code that is generic over `ZeebeGateway` (or takes a `dyn ZeebeGateway`) and supply
+/// their own test doubles.
+///
+/// The trait is object safe, so every method returns a boxed future or stream. The methods mirror
+/// the inherent methods on `Client`: `topology`, `deploy_bpmn_workflow`, `deploy`,
+/// `create_workflow_instance`, `activate_jobs`, `complete_job`, `fail_job`, `publish_message`,
+/// `cancel_workflow_instance`, `set_variables_json`, `update_job_retries` and `resolve_incident`.
+/// `activated_jobs` is provided on top of `activate_jobs`.
+pub trait ZeebeGateway {
+    /// Get the topology of the cluster.
+    fn topology(&self) -> Pin<Box<dyn Future<Output = Result<Topology, Error>> + Send>>;
+
+    /// Deploy a single bpmn workflow.
+    fn deploy_bpmn_workflow(
+        &self,
+        workflow_name: String,
+        workflow_definition: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>>;
+
+    /// Deploy several resources at once.
+    fn deploy(
+        &self,
+        deploy_request: DeployRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>>;
+
+    /// Create a workflow instance.
+    fn create_workflow_instance(
+        &self,
+        workflow_instance: WorkflowInstance,
+    ) -> Pin<Box<dyn Future<Output = Result<CreatedWorkflowInstance, Error>> + Send>>;
+
+    /// Activate jobs.
+    fn activate_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> Pin<Box<dyn Stream<Item = Result<ActivatedJobs, Error>> + Send>>;
+
+    /// Activate jobs one at a time, see `Client::activated_jobs`.
+    fn activated_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> Pin<Box<dyn Stream<Item = Result<ActivatedJob, Error>> + Send>> {
+        flatten_batches(self.activate_jobs(jobs_config)).boxed()
+    }
+
+    /// Complete a job.
+    fn complete_job(
+        &self,
+        complete_job: CompleteJob,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Fail a job.
+    fn fail_job(
+        &self,
+        job_key: i64,
+        retries: i32,
+        error_message: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Publish a message.
+    fn publish_message(
+        &self,
+        publish_message: PublishMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Cancel a running workflow instance.
+    fn cancel_workflow_instance(
+        &self,
+        workflow_instance_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Set variables, given as a JSON document, on the scope of an element instance.
+    fn set_variables_json(
+        &self,
+        element_instance_key: i64,
+        variables: String,
+        local: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Set the retries of a job.
+    fn update_job_retries(
+        &self,
+        job_key: i64,
+        retries: i32,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// Resolve an incident.
+    fn resolve_incident(
+        &self,
+        incident_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    /// The metrics that workers using this gateway record into. `Client` returns the metrics it
+    /// was built with.
+    #[cfg(feature = "metrics")]
+    fn metrics(&self) -> Option<&crate::Metrics> {
+        None
+    }
+}
+
+fn flatten_batches<S>(batches: S) -> impl Stream<Item = Result<ActivatedJob, Error>> + Send
+where
+    S: Stream<Item = Result<ActivatedJobs, Error>> + Send,
+{
+    batches
+        .map(|batch| match batch {
+            Ok(batch) => {
+                futures::stream::iter(batch.activated_jobs.into_iter().map(Ok)).left_stream()
+            }
+            Err(e) => futures::stream::once(futures::future::err(e)).right_stream(),
+        })
+        .flatten()
+}
+
+impl ZeebeGateway for Client {
+    fn topology(&self) -> Pin<Box<dyn Future<Output = Result<Topology, Error>> + Send>> {
+        Client::topology(self).boxed()
+    }
+
+    fn deploy_bpmn_workflow(
+        &self,
+        workflow_name: String,
+        workflow_definition: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        Client::deploy_bpmn_workflow(self, workflow_name, workflow_definition).boxed()
+    }
+
+    fn deploy(
+        &self,
+        deploy_request: DeployRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        Client::deploy(self, deploy_request).boxed()
+    }
+
+    fn create_workflow_instance(
+        &self,
+        workflow_instance: WorkflowInstance,
+    ) -> Pin<Box<dyn Future<Output = Result<CreatedWorkflowInstance, Error>> + Send>> {
+        Client::create_workflow_instance(self, workflow_instance).boxed()
+    }
+
+    fn activate_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> Pin<Box<dyn Stream<Item = Result<ActivatedJobs, Error>> + Send>> {
+        Client::activate_jobs(self, jobs_config).boxed()
+    }
+
+    fn complete_job(
+        &self,
+        complete_job: CompleteJob,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::complete_job(self, complete_job).boxed()
+    }
+
+    fn fail_job(
+        &self,
+        job_key: i64,
+        retries: i32,
+        error_message: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::fail_job(self, job_key, retries, error_message).boxed()
+    }
+
+    fn publish_message(
+        &self,
+        publish_message: PublishMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::publish_message(self, publish_message).boxed()
+    }
+
+    fn cancel_workflow_instance(
+        &self,
+        workflow_instance_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::cancel_workflow_instance(self, workflow_instance_key).boxed()
+    }
+
+    fn set_variables_json(
+        &self,
+        element_instance_key: i64,
+        variables: String,
+        local: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::set_variables_json(self, element_instance_key, variables, local).boxed()
+    }
+
+    fn update_job_retries(
+        &self,
+        job_key: i64,
+        retries: i32,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::update_job_retries(self, job_key, retries).boxed()
+    }
+
+    fn resolve_incident(
+        &self,
+        incident_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        Client::resolve_incident(self, incident_key).boxed()
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics(&self) -> Option<&crate::Metrics> {
+        self.metrics.as_ref()
+    }
+}
+
+impl<G: ZeebeGateway + ?Sized> ZeebeGateway for Arc<G> {
+    fn topology(&self) -> Pin<Box<dyn Future<Output = Result<Topology, Error>> + Send>> {
+        (**self).topology()
+    }
+
+    fn deploy_bpmn_workflow(
+        &self,
+        workflow_name: String,
+        workflow_definition: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        (**self).deploy_bpmn_workflow(workflow_name, workflow_definition)
+    }
+
+    fn deploy(
+        &self,
+        deploy_request: DeployRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        (**self).deploy(deploy_request)
+    }
+
+    fn create_workflow_instance(
+        &self,
+        workflow_instance: WorkflowInstance,
+    ) -> Pin<Box<dyn Future<Output = Result<CreatedWorkflowInstance, Error>> + Send>> {
+        (**self).create_workflow_instance(workflow_instance)
+    }
+
+    fn activate_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> Pin<Box<dyn Stream<Item = Result<ActivatedJobs, Error>> + Send>> {
+        (**self).activate_jobs(jobs_config)
+    }
+
+    fn complete_job(
+        &self,
+        complete_job: CompleteJob,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).complete_job(complete_job)
+    }
+
+    fn fail_job(
+        &self,
+        job_key: i64,
+        retries: i32,
+        error_message: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).fail_job(job_key, retries, error_message)
+    }
+
+    fn publish_message(
+        &self,
+        publish_message: PublishMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).publish_message(publish_message)
+    }
+
+    fn cancel_workflow_instance(
+        &self,
+        workflow_instance_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).cancel_workflow_instance(workflow_instance_key)
+    }
+
+    fn set_variables_json(
+        &self,
+        element_instance_key: i64,
+        variables: String,
+        local: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).set_variables_json(element_instance_key, variables, local)
+    }
+
+    fn update_job_retries(
+        &self,
+        job_key: i64,
+        retries: i32,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).update_job_retries(job_key, retries)
+    }
+
+    fn resolve_incident(
+        &self,
+        incident_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        (**self).resolve_incident(incident_key)
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics(&self) -> Option<&crate::Metrics> {
+        (**self).metrics()
     }
 }
 
@@ -178,6 +1065,9 @@ impl Client {
 #[derive(Debug)]
 pub struct Topology {
     pub brokers: Vec<BrokerInfo>,
+    pub cluster_size: i32,
+    pub partitions_count: i32,
+    pub replication_factor: i32,
 }
 
 impl Topology {
@@ -188,15 +1078,36 @@ impl Topology {
                 .into_iter()
                 .map(From::from)
                 .collect(),
+            cluster_size: topology_response.clusterSize,
+            partitions_count: topology_response.partitionsCount,
+            replication_factor: topology_response.replicationFactor,
         }
     }
+
+    /// The ids of all partitions that currently have a leader, sorted and deduplicated.
+    pub fn leader_partitions(&self) -> Vec<i32> {
+        let mut leaders: Vec<i32> = self
+            .brokers
+            .iter()
+            .flat_map(|b| b.partitions.iter())
+            .filter(|p| p.role == BrokerRole::LEADER)
+            .map(|p| p.partition_id)
+            .collect();
+        leaders.sort();
+        leaders.dedup();
+        leaders
+    }
+
+    /// True if the cluster reports at least one partition and each of them has a leader.
+    pub fn is_healthy(&self) -> bool {
+        self.partitions_count > 0
+            && self.leader_partitions().len() >= self.partitions_count as usize
+    }
 }
 
 impl From<gateway::TopologyResponse> for Topology {
     fn from(tr: gateway::TopologyResponse) -> Self {
-        Self {
-            brokers: tr.brokers.into_iter().map(From::from).collect(),
-        }
+        Topology::new(tr)
     }
 }
 
@@ -237,7 +1148,7 @@ impl From<gateway::Partition> for Partition {
 }
 
 /// Is this broker a leader or not?
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BrokerRole {
     LEADER = 0,
     FOLLOWER = 1,
@@ -252,6 +1163,141 @@ impl From<gateway::Partition_PartitionBrokerRole> for BrokerRole {
     }
 }
 
+/// The type of a deployed resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceType {
+    /// Let the gateway detect the type from the extension of the resource name.
+    File,
+    Bpmn,
+    Yaml,
+}
+
+impl Into<gateway::WorkflowRequestObject_ResourceType> for ResourceType {
+    fn into(self) -> gateway::WorkflowRequestObject_ResourceType {
+        match self {
+            ResourceType::File => gateway::WorkflowRequestObject_ResourceType::FILE,
+            ResourceType::Bpmn => gateway::WorkflowRequestObject_ResourceType::BPMN,
+            ResourceType::Yaml => gateway::WorkflowRequestObject_ResourceType::YAML,
+        }
+    }
+}
+
+/// A resource of a deployment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeployResource {
+    pub name: String,
+    pub resource_type: ResourceType,
+    pub definition: Vec<u8>,
+}
+
+/// Describes a deployment of one or more workflow resources. Zeebe deploys all of them or none.
+///
+/// ```rust,ignore
+/// let deploy_request = DeployRequest::new()
+///     .bpmn("order-process.bpmn", order_process)
+///     .yaml("payment.yaml", payment);
+/// let deployed = block_on(client.deploy(deploy_request))?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeployRequest {
+    resources: Vec<DeployResource>,
+}
+
+impl DeployRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a resource named `name`, e.g. `order-process.bpmn`.
+    pub fn resource<S: Into<String>>(
+        mut self,
+        name: S,
+        resource_type: ResourceType,
+        definition: Vec<u8>,
+    ) -> Self {
+        self.resources.push(DeployResource {
+            name: name.into(),
+            resource_type,
+            definition,
+        });
+        self
+    }
+
+    /// Add a BPMN resource.
+    pub fn bpmn<S: Into<String>>(self, name: S, definition: Vec<u8>) -> Self {
+        self.resource(name, ResourceType::Bpmn, definition)
+    }
+
+    /// Add a YAML workflow resource.
+    pub fn yaml<S: Into<String>>(self, name: S, definition: Vec<u8>) -> Self {
+        self.resource(name, ResourceType::Yaml, definition)
+    }
+
+    /// Add the file at `path` as a resource named after the file. Files ending in `.bpmn` are
+    /// deployed as BPMN and files ending in `.yaml` or `.yml` as YAML; the gateway detects the
+    /// type of any other file from its name.
+    pub fn path<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let resource_error = |e: std::io::Error| Error::ResourceError(path.to_path_buf(), e);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| resource_error(std::io::ErrorKind::InvalidInput.into()))?;
+        let resource_type = match path.extension().and_then(|e| e.to_str()) {
+            Some("bpmn") => ResourceType::Bpmn,
+            Some("yaml") | Some("yml") => ResourceType::Yaml,
+            _ => ResourceType::File,
+        };
+        let definition = std::fs::read(path).map_err(resource_error)?;
+        Ok(self.resource(name, resource_type, definition))
+    }
+
+    /// Add every `.bpmn`, `.yaml` and `.yml` file directly inside `dir`, in the order of their
+    /// names. Subdirectories are not searched.
+    pub fn dir<P: AsRef<Path>>(self, dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let resource_error = |e: std::io::Error| Error::ResourceError(dir.to_path_buf(), e);
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(resource_error)? {
+            let path = entry.map_err(resource_error)?.path();
+            let is_workflow = match path.extension().and_then(|e| e.to_str()) {
+                Some("bpmn") | Some("yaml") | Some("yml") => path.is_file(),
+                _ => false,
+            };
+            if is_workflow {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths
+            .into_iter()
+            .try_fold(self, |request, path| request.path(path))
+    }
+
+    pub fn resources(&self) -> &[DeployResource] {
+        &self.resources
+    }
+}
+
+impl Into<gateway::DeployWorkflowRequest> for DeployRequest {
+    fn into(self) -> gateway::DeployWorkflowRequest {
+        let workflows = self
+            .resources
+            .into_iter()
+            .map(|resource| {
+                let mut workflow_request_object = gateway::WorkflowRequestObject::default();
+                workflow_request_object.set_name(resource.name);
+                workflow_request_object.set_field_type(resource.resource_type.into());
+                workflow_request_object.set_definition(resource.definition);
+                workflow_request_object
+            })
+            .collect();
+        let mut deploy_workflow_request = gateway::DeployWorkflowRequest::default();
+        deploy_workflow_request.set_workflows(protobuf::RepeatedField::from_vec(workflows));
+        deploy_workflow_request
+    }
+}
+
 /// Describes a collection of deployed workflows.
 #[derive(Debug)]
 pub struct DeployedWorkflows {
@@ -293,12 +1339,15 @@ impl From<gateway::WorkflowMetadata> for Workflow {
 }
 
 /// Describes a workflow that was instantiated on zeebe.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CreatedWorkflowInstance {
-    workflow_key: i64,
-    bpmn_process_id: String,
-    version: i32,
-    workflow_instance_key: i64,
+    /// the key of the workflow the instance was created from
+    pub workflow_key: i64,
+    pub bpmn_process_id: String,
+    /// the version of the workflow, i.e. the latest version if `WorkflowVersion::Latest` was used
+    pub version: i32,
+    /// the key to cancel the instance or to set variables on it with
+    pub workflow_instance_key: i64,
 }
 
 impl CreatedWorkflowInstance {
@@ -341,8 +1390,9 @@ impl WorkflowInstance {
         }
     }
 
-    pub fn variables<S: Serialize>(mut self, variables: &S) -> Result<Self, serde_json::Error> {
-        serde_json::to_string(variables).map(move |v| {
+    /// Start the instance with `variables`, which must serialize to a JSON object.
+    pub fn variables<S: Serialize>(mut self, variables: &S) -> Result<Self, Error> {
+        serialize_variables(variables).map(move |v| {
             self.variables = Some(v);
             self
         })
@@ -369,6 +1419,15 @@ impl Into<gateway::CreateWorkflowInstanceRequest> for WorkflowInstance {
 }
 
 /// A message for publishing an event on zeebe.
+///
+/// ```rust,ignore
+/// let publish_message = PublishMessage::with_correlation_key("payment-confirmed", "order-10")
+///     .time_to_live(Duration::from_secs(60))
+///     .variables(&payment)?;
+/// ```
+///
+/// Without a message id zeebe accepts any number of messages with the same name and correlation
+/// key; with one, a second message with the same id is rejected while the first is buffered.
 pub struct PublishMessage {
     name: String,
     correlation_key: String,
@@ -393,13 +1452,34 @@ impl PublishMessage {
         }
     }
 
+    /// A message without a message id that is not buffered, i.e. it is only correlated to
+    /// workflow instances that are waiting for it when it is published.
+    pub fn with_correlation_key<S1: Into<String>, S2: Into<String>>(
+        name: S1,
+        correlation_key: S2,
+    ) -> Self {
+        PublishMessage::new(name, correlation_key, 0, "")
+    }
+
+    /// How long the message is buffered for workflow instances that start waiting for it later.
+    /// Durations beyond what the gateway can represent are capped.
+    pub fn time_to_live(mut self, time_to_live: Duration) -> Self {
+        self.time_to_live = saturating_millis(time_to_live);
+        self
+    }
+
+    /// The id that makes the message unique while it is buffered.
+    pub fn message_id<S: Into<String>>(mut self, message_id: S) -> Self {
+        self.message_id = message_id.into();
+        self
+    }
+
+    /// Attach `variables`, which must serialize to a JSON object.
     pub fn variables<S: Serialize>(mut self, variables: &S) -> Result<Self, Error> {
-        serde_json::to_string(variables)
-            .map_err(|e| Error::JsonError(e))
-            .map(move |v| {
-                self.variables = Some(v);
-                self
-            })
+        serialize_variables(variables).map(move |v| {
+            self.variables = Some(v);
+            self
+        })
     }
 }
 
@@ -417,8 +1497,78 @@ impl Into<gateway::PublishMessageRequest> for PublishMessage {
     }
 }
 
+/// A message with typed variables, published with `Client::publish`. The correlation key can be
+/// anything that implements `Display`, like an order id, and the variables anything that
+/// serializes to a JSON object:
+///
+/// ```rust,ignore
+/// let message = Message::new("payment-confirmed", order_id)
+///     .time_to_live(Duration::from_secs(60))
+///     .message_id(format!("payment-{}", payment_id))
+///     .variables(Payment { total: 25.95 });
+/// client.publish(message)
+/// ```
+#[derive(Clone, Debug)]
+pub struct Message<T> {
+    name: String,
+    correlation_key: String,
+    time_to_live: Duration,
+    message_id: Option<String>,
+    variables: Option<T>,
+}
+
+impl Message<()> {
+    /// A message without variables that is not buffered and has no message id.
+    pub fn new<S: Into<String>, K: std::fmt::Display>(name: S, correlation_key: K) -> Self {
+        Message {
+            name: name.into(),
+            correlation_key: correlation_key.to_string(),
+            time_to_live: Duration::from_secs(0),
+            message_id: None,
+            variables: None,
+        }
+    }
+}
+
+impl<T: Serialize> Message<T> {
+    /// How long the message is buffered for workflow instances that start waiting for it later.
+    pub fn time_to_live(mut self, time_to_live: Duration) -> Self {
+        self.time_to_live = time_to_live;
+        self
+    }
+
+    /// The id that makes the message unique while it is buffered.
+    pub fn message_id<S: Into<String>>(mut self, message_id: S) -> Self {
+        self.message_id = Some(message_id.into());
+        self
+    }
+
+    /// Attach `variables`, which must serialize to a JSON object when the message is published.
+    pub fn variables<V: Serialize>(self, variables: V) -> Message<V> {
+        Message {
+            name: self.name,
+            correlation_key: self.correlation_key,
+            time_to_live: self.time_to_live,
+            message_id: self.message_id,
+            variables: Some(variables),
+        }
+    }
+
+    /// Serialize the message into a `PublishMessage`, e.g. to publish it through any
+    /// `ZeebeGateway`. Fails if the variables do not serialize to a JSON object.
+    pub fn into_publish_message(self) -> Result<PublishMessage, Error> {
+        let publish_message = PublishMessage::with_correlation_key(self.name, self.correlation_key)
+            .time_to_live(self.time_to_live)
+            .message_id(self.message_id.unwrap_or_default());
+        match &self.variables {
+            Some(variables) => publish_message.variables(variables),
+            None => Ok(publish_message),
+        }
+    }
+}
+
 /// A message for completing a zeebe job.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CompleteJob {
     pub job_key: i64,
     pub variables: Option<String>,
@@ -429,13 +1579,12 @@ impl CompleteJob {
         Self { job_key, variables }
     }
 
+    /// Complete the job with `variables`, which must serialize to a JSON object.
     pub fn variables<S: Serialize>(mut self, variables: &S) -> Result<Self, Error> {
-        serde_json::to_string(variables)
-            .map_err(|e| Error::JsonError(e))
-            .map(move |v| {
-                self.variables = Some(v);
-                self
-            })
+        serialize_variables(variables).map(move |v| {
+            self.variables = Some(v);
+            self
+        })
     }
 }
 
@@ -461,6 +1610,11 @@ pub struct ActivateJobs {
     pub timeout: i64,
     /// the maximum jobs to activate by this request
     pub max_jobs_to_activate: i32,
+    /// how long the gateway holds the request open until at least one job is activated, in
+    /// milliseconds; `0` uses the gateway default and a negative value disables long polling
+    pub request_timeout: i64,
+    /// the variables to fetch with the jobs; all variables are fetched if this is empty
+    pub fetch_variables: Vec<String>,
 }
 
 impl ActivateJobs {
@@ -475,8 +1629,33 @@ impl ActivateJobs {
             job_type: job_type.into(),
             timeout,
             max_jobs_to_activate,
+            request_timeout: 0,
+            fetch_variables: Vec::new(),
         }
     }
+
+    /// Only fetch these variables with the activated jobs instead of all variables in scope.
+    pub fn fetch_variables<I, S>(mut self, fetch_variables: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fetch_variables = fetch_variables.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Long poll for jobs: the gateway completes the request once at least one job is activated
+    /// or after `request_timeout`.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = saturating_millis(request_timeout);
+        self
+    }
+
+    /// Complete the request immediately, even if no job was activated.
+    pub fn without_long_polling(mut self) -> Self {
+        self.request_timeout = -1;
+        self
+    }
 }
 
 impl Into<gateway::ActivateJobsRequest> for ActivateJobs {
@@ -486,6 +1665,9 @@ impl Into<gateway::ActivateJobsRequest> for ActivateJobs {
         activate_jobs_request.set_timeout(self.timeout);
         activate_jobs_request.set_worker(self.worker);
         activate_jobs_request.set_field_type(self.job_type);
+        activate_jobs_request.set_requestTimeout(self.request_timeout);
+        activate_jobs_request
+            .set_fetchVariable(protobuf::RepeatedField::from(self.fetch_variables));
         activate_jobs_request
     }
 }
@@ -504,12 +1686,29 @@ impl ActivatedJobs {
 }
 
 /// Describes an activate zeebe job. Use this to do work and respond with completion or failure.
-#[derive(Clone, Debug)]
+///
+/// Besides the variables, a job carries where it comes from: the workflow instance, the
+/// workflow definition and the service task element. The job key is unique, so it makes a good
+/// idempotency key for side effects of the handler.
+#[derive(Clone, Debug, Default)]
 pub struct ActivatedJob {
     /// the key, a unique identifier for the job
     pub key: i64,
     /// the type of the job (should match what was requested)
     pub field_type: String,
+    /// the job's workflow instance key
+    pub workflow_instance_key: i64,
+    /// the bpmn process ID of the job workflow definition
+    pub bpmn_process_id: String,
+    /// the version of the job workflow definition
+    pub workflow_definition_version: i32,
+    /// the key of the job workflow definition
+    pub workflow_key: i64,
+    /// the associated task element ID
+    pub element_id: String,
+    /// the unique key identifying the associated task, unique within the scope of the workflow
+    /// instance
+    pub element_instance_key: i64,
     /// a set of custom headers defined during modelling; returned as a serialized JSON document
     pub custom_headers: String,
     /// the name of the worker which activated this job
@@ -522,6 +1721,26 @@ pub struct ActivatedJob {
     pub variables: String,
 }
 
+impl ActivatedJob {
+    /// Deserialize the job variables into `T`. Fails with `Error::JsonDeserializationError`,
+    /// which names the offending field and position, if the variables do not match `T`.
+    pub fn variables_as<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_str(&self.variables).map_err(|e| Error::JsonDeserializationError(e))
+    }
+
+    /// Deserialize the custom headers of the service task into `T`, e.g. a struct with a field
+    /// per header that parametrizes a generic worker.
+    pub fn custom_headers_as<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_str(&self.custom_headers).map_err(|e| Error::JsonDeserializationError(e))
+    }
+
+    /// The value of the custom header `name`, if the service task defines it.
+    pub fn get_header(&self, name: &str) -> Option<String> {
+        let headers: serde_json::Value = serde_json::from_str(&self.custom_headers).ok()?;
+        headers.get(name)?.as_str().map(str::to_string)
+    }
+}
+
 impl From<gateway::ActivatedJob> for ActivatedJob {
     fn from(aj: gateway::ActivatedJob) -> Self {
         ActivatedJob {
@@ -532,6 +1751,560 @@ impl From<gateway::ActivatedJob> for ActivatedJob {
             deadline: aj.deadline,
             custom_headers: aj.customHeaders,
             field_type: aj.field_type,
+            workflow_instance_key: aj.workflowInstanceKey,
+            bpmn_process_id: aj.bpmnProcessId,
+            workflow_definition_version: aj.workflowDefinitionVersion,
+            workflow_key: aj.workflowKey,
+            element_id: aj.elementId,
+            element_instance_key: aj.elementInstanceKey,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::gateway;
+    use crate::mock_gateway::MockGateway;
+    use crate::{
+        ActivateJobs, ActivatedJob, ActivatedJobs, BackpressureRetry, CompleteJob,
+        CredentialsProvider, DeployRequest, Error, ErrorKind, Message, PublishMessage,
+        RequestMetadata, RetryPolicy, WorkflowInstance, WorkflowVersion, ZeebeGateway,
+    };
+    use futures::{Future, FutureExt, StreamExt, TryFutureExt};
+    use gateway::WorkflowRequestObject_ResourceType;
+    use std::collections::BTreeMap;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn complete_all<G: ZeebeGateway + ?Sized>(gateway: &G, keys: &[i64]) {
+        for &key in keys {
+            futures::executor::block_on(gateway.complete_job(CompleteJob::new(key, None))).unwrap();
+        }
+    }
+
+    struct StaticCredentials;
+
+    impl CredentialsProvider for StaticCredentials {
+        fn authorization(&self) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>> {
+            futures::future::ok("Bearer abc".to_string()).boxed()
+        }
+    }
+
+    #[test]
+    fn attaches_credentials_to_calls() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        futures::executor::block_on(client.complete_job(CompleteJob::new(1, None))).unwrap();
+        let client = client.with_credentials(StaticCredentials);
+        futures::executor::block_on(client.complete_job(CompleteJob::new(2, None))).unwrap();
+        assert_eq!(
+            *mock.authorizations.lock().unwrap(),
+            vec![None, Some("Bearer abc".to_string())]
+        );
+    }
+
+    #[test]
+    fn overrides_the_deadline_per_call() {
+        let client = MockGateway::default()
+            .client()
+            .with_request_timeout(Duration::from_secs(10));
+        let overridden = client
+            .clone()
+            .with_request_timeout(Duration::from_millis(10));
+        assert_eq!(client.request_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(overridden.request_timeout, Some(Duration::from_millis(10)));
+        assert_eq!(client.without_request_timeout().request_timeout, None);
+    }
+
+    #[test]
+    fn deadline_fails_slow_calls() {
+        let slow = futures::future::pending::<Result<(), Error>>().boxed();
+        let result =
+            futures::executor::block_on(super::with_deadline(slow, Duration::from_millis(10)));
+        match result {
+            Err(Error::DeadlineExceeded) => {}
+            other => panic!("expected deadline to be exceeded, got {:?}", other),
+        }
+        let fast = futures::future::ok::<_, Error>(1).boxed();
+        let result =
+            futures::executor::block_on(super::with_deadline(fast, Duration::from_secs(10)));
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    fn job_with_variables(variables: &str) -> ActivatedJob {
+        ActivatedJob {
+            key: 0,
+            field_type: "".to_string(),
+            custom_headers: "".to_string(),
+            worker: "".to_string(),
+            retries: 0,
+            deadline: 0,
+            variables: variables.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn converts_the_job_metadata() {
+        let mut job = gateway::ActivatedJob::default();
+        job.set_key(1);
+        job.set_workflowInstanceKey(2);
+        job.set_bpmnProcessId("order-process".to_string());
+        job.set_workflowDefinitionVersion(3);
+        job.set_workflowKey(4);
+        job.set_elementId("collect-money".to_string());
+        job.set_elementInstanceKey(5);
+        let job = ActivatedJob::from(job);
+        assert_eq!((job.key, job.workflow_instance_key), (1, 2));
+        assert_eq!(job.bpmn_process_id, "order-process");
+        assert_eq!((job.workflow_definition_version, job.workflow_key), (3, 4));
+        assert_eq!(job.element_id, "collect-money");
+        assert_eq!(job.element_instance_key, 5);
+    }
+
+    #[test]
+    fn reads_custom_headers() {
+        let job = ActivatedJob {
+            custom_headers: r#"{"method": "POST", "url": "http://example.com"}"#.to_string(),
+            ..job_with_variables("{}")
+        };
+        assert_eq!(job.get_header("url").unwrap(), "http://example.com");
+        assert_eq!(job.get_header("timeout"), None);
+        let headers: BTreeMap<String, String> = job.custom_headers_as().unwrap();
+        assert_eq!(headers["method"], "POST");
+    }
+
+    #[test]
+    fn flattens_activated_batches_into_jobs() {
+        let job = |key| ActivatedJob {
+            key,
+            ..job_with_variables("{}")
+        };
+        let batches = futures::stream::iter(vec![
+            Ok(ActivatedJobs {
+                activated_jobs: vec![job(1), job(2)],
+            }),
+            Ok(ActivatedJobs {
+                activated_jobs: vec![],
+            }),
+            Err(Error::DeadlineExceeded),
+            Ok(ActivatedJobs {
+                activated_jobs: vec![job(3)],
+            }),
+        ]);
+        let jobs: Vec<_> = futures::executor::block_on(super::flatten_batches(batches).collect());
+        let keys: Vec<_> = jobs
+            .into_iter()
+            .map(|job| job.map(|job| job.key).map_err(|e| e.kind()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![Ok(1), Ok(2), Err(ErrorKind::DeadlineExceeded), Ok(3)]
+        );
+
+        let mock = MockGateway::with_jobs(2);
+        let activate_jobs = ActivateJobs::new("rusty-worker", "payment-service", 10, 5);
+        let jobs: Vec<_> =
+            futures::executor::block_on(mock.client().activated_jobs(activate_jobs).collect());
+        assert_eq!(jobs.len(), 2);
+    }
+
+    #[test]
+    fn deserializes_typed_variables() {
+        let job = job_with_variables(r#"{"orderId": 10, "total": 25.5}"#);
+        let variables: BTreeMap<String, f64> = job.variables_as().unwrap();
+        assert_eq!(variables["orderId"], 10.0);
+        assert_eq!(variables["total"], 25.5);
+    }
+
+    #[test]
+    fn reports_variables_mismatch() {
+        let job = job_with_variables(r#"{"orderId": "ten"}"#);
+        match job.variables_as::<BTreeMap<String, i32>>() {
+            Err(Error::JsonDeserializationError(e)) => assert!(e.to_string().contains("line 1")),
+            other => panic!("expected a deserialization error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancels_workflow_instance() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        futures::executor::block_on(client.cancel_workflow_instance(42)).unwrap();
+        let cancelled = mock.cancelled.lock().unwrap();
+        assert_eq!(cancelled[0].workflowInstanceKey, 42);
+    }
+
+    #[test]
+    fn sets_variables() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        let mut variables = BTreeMap::new();
+        variables.insert("orderId", 10);
+        futures::executor::block_on(client.set_variables(1, &variables, true)).unwrap();
+        futures::executor::block_on(client.set_variables_json(2, r#"{"paid": true}"#, false))
+            .unwrap();
+        let requests = mock.variables.lock().unwrap();
+        assert_eq!(requests[0].elementInstanceKey, 1);
+        assert_eq!(requests[0].variables, r#"{"orderId":10}"#);
+        assert!(requests[0].local);
+        assert_eq!(requests[1].variables, r#"{"paid":true}"#);
+        assert!(!requests[1].local);
+    }
+
+    #[test]
+    fn rejects_non_object_variables() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        let result = futures::executor::block_on(client.set_variables_json(1, "[1, 2]", false));
+        match result {
+            Err(Error::VariablesNotAnObject(_)) => {}
+            other => panic!(
+                "expected non-object variables to be rejected, got {:?}",
+                other
+            ),
+        }
+        assert!(mock.variables.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn builds_messages_without_a_message_id() {
+        let mut variables = BTreeMap::new();
+        variables.insert("total", 10);
+        let request: gateway::PublishMessageRequest =
+            PublishMessage::with_correlation_key("payment-confirmed", "order-10")
+                .time_to_live(Duration::from_secs(60))
+                .variables(&variables)
+                .unwrap()
+                .into();
+        assert_eq!(request.name, "payment-confirmed");
+        assert_eq!(request.correlationKey, "order-10");
+        assert_eq!(request.timeToLive, 60_000);
+        assert_eq!(request.messageId, "");
+        assert_eq!(request.variables, r#"{"total":10}"#);
+
+        let not_an_object = PublishMessage::with_correlation_key("payment-confirmed", "order-10")
+            .variables(&vec![1, 2, 3]);
+        assert!(not_an_object.is_err());
+    }
+
+    #[test]
+    fn rejects_completions_with_invalid_variables() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        let complete_job = CompleteJob::new(1, Some("\"done\"".to_string()));
+        match futures::executor::block_on(client.complete_job(complete_job)) {
+            Err(Error::VariablesNotAnObject(kind)) => assert_eq!(kind, "string"),
+            _ => panic!("expected the variables to be rejected"),
+        }
+        assert!(CompleteJob::new(1, None).variables(&vec![1]).is_err());
+        // an empty document means no variables
+        let complete_job = CompleteJob::new(1, Some(" ".to_string()));
+        assert!(futures::executor::block_on(client.complete_job(complete_job)).is_ok());
+    }
+
+    #[test]
+    fn builds_typed_messages() {
+        let mut variables = BTreeMap::new();
+        variables.insert("total", 10);
+        let request: gateway::PublishMessageRequest = Message::new("payment-confirmed", 10)
+            .time_to_live(Duration::from_secs(60))
+            .message_id("payment-3")
+            .variables(variables)
+            .into_publish_message()
+            .unwrap()
+            .into();
+        assert_eq!(request.name, "payment-confirmed");
+        assert_eq!(request.correlationKey, "10");
+        assert_eq!(request.timeToLive, 60_000);
+        assert_eq!(request.messageId, "payment-3");
+        assert_eq!(request.variables, r#"{"total":10}"#);
+
+        let request: gateway::PublishMessageRequest = Message::new("payment-confirmed", "order-10")
+            .into_publish_message()
+            .unwrap()
+            .into();
+        assert_eq!(request.messageId, "");
+        assert_eq!(request.variables, "");
+
+        let not_an_object = Message::new("payment-confirmed", 10)
+            .variables(vec![1, 2, 3])
+            .into_publish_message();
+        assert!(not_an_object.is_err());
+
+        let request: gateway::PublishMessageRequest = Message::new("payment-confirmed", 10)
+            .time_to_live(Duration::new(u64::max_value(), 0))
+            .into_publish_message()
+            .unwrap()
+            .into();
+        assert_eq!(request.timeToLive, i64::max_value());
+    }
+
+    #[test]
+    fn creates_instances_with_typed_variables() {
+        let mock = MockGateway::default();
+        let mut variables = BTreeMap::new();
+        variables.insert("orderId", 10);
+        let workflow_instance = WorkflowInstance::workflow_instance_with_bpmn_process(
+            "order-process",
+            WorkflowVersion::Latest,
+        )
+        .variables(&variables)
+        .unwrap();
+        let created =
+            futures::executor::block_on(mock.client().create_workflow_instance(workflow_instance))
+                .unwrap();
+        assert_eq!(created.bpmn_process_id, "order-process");
+        assert_eq!(created.workflow_instance_key, 1);
+        let requests = mock.created.lock().unwrap();
+        assert_eq!(requests[0].version, -1);
+        assert_eq!(requests[0].variables, r#"{"orderId":10}"#);
+
+        let not_an_object =
+            WorkflowInstance::workflow_instance_with_workflow_key(1).variables(&"order-10");
+        assert!(not_an_object.is_err());
+    }
+
+    #[test]
+    fn creates_instances_in_bulk() {
+        let mock = MockGateway::default();
+        let workflow_instances = (0..5).map(|version| {
+            WorkflowInstance::workflow_instance_with_bpmn_process(
+                "order-process",
+                WorkflowVersion::Version(version + 1),
+            )
+        });
+        let created = futures::executor::block_on(
+            mock.client()
+                .create_workflow_instances(workflow_instances, 2),
+        );
+        let versions: Vec<_> = created
+            .into_iter()
+            .map(|created| created.unwrap().version)
+            .collect();
+        assert_eq!(versions, vec![1, 2, 3, 4, 5]);
+        assert_eq!(mock.created.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn creates_at_most_concurrency_instances_at_once() {
+        let mock = MockGateway::default();
+        *mock.create_delay.lock().unwrap() = Some(Duration::from_millis(5));
+        let workflow_instances = (0..6).map(|_| {
+            WorkflowInstance::workflow_instance_with_bpmn_process(
+                "order-process",
+                WorkflowVersion::Latest,
+            )
+        });
+        let created = futures::executor::block_on(
+            mock.client()
+                .create_workflow_instances(workflow_instances, 2),
+        );
+        assert!(created.iter().all(Result::is_ok));
+        assert_eq!(*mock.creates_in_flight.lock().unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn deploys_several_resources_at_once() {
+        let mock = MockGateway::default();
+        let deploy_request = DeployRequest::new()
+            .bpmn("order-process.bpmn", b"<bpmn/>".to_vec())
+            .yaml("payment.yaml", b"name: payment".to_vec());
+        let deployed = futures::executor::block_on(mock.client().deploy(deploy_request)).unwrap();
+
+        let requests = mock.deployed.lock().unwrap();
+        let resources: Vec<_> = requests[0]
+            .workflows
+            .iter()
+            .map(|w| (w.name.as_str(), w.field_type, w.definition.len()))
+            .collect();
+        assert_eq!(
+            resources,
+            vec![
+                (
+                    "order-process.bpmn",
+                    WorkflowRequestObject_ResourceType::BPMN,
+                    7
+                ),
+                ("payment.yaml", WorkflowRequestObject_ResourceType::YAML, 13),
+            ]
+        );
+        let names: Vec<_> = deployed
+            .workflows
+            .iter()
+            .map(|w| (w.resource_name.as_str(), w.version))
+            .collect();
+        assert_eq!(names, vec![("order-process.bpmn", 1), ("payment.yaml", 1)]);
+    }
+
+    #[test]
+    fn deploys_the_workflows_of_a_directory() {
+        let dir = std::env::temp_dir().join(format!("zeebest-deploy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("payment.yaml"), "name: payment").unwrap();
+        std::fs::write(dir.join("order-process.bpmn"), "<bpmn/>").unwrap();
+        std::fs::write(dir.join("README.md"), "not a workflow").unwrap();
+
+        let mock = MockGateway::default();
+        let deployed = futures::executor::block_on(mock.client().deploy_from_dir(&dir));
+        let missing = DeployRequest::new().path(dir.join("missing.bpmn"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = deployed
+            .unwrap()
+            .workflows
+            .into_iter()
+            .map(|w| w.resource_name)
+            .collect();
+        assert_eq!(names, vec!["order-process.bpmn", "payment.yaml"]);
+        assert_eq!(
+            mock.deployed.lock().unwrap()[0].workflows[1].field_type,
+            WorkflowRequestObject_ResourceType::YAML
+        );
+        match missing {
+            Err(Error::ResourceError(path, _)) => assert!(path.ends_with("missing.bpmn")),
+            other => panic!("expected a resource error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn updates_retries_and_resolves_incident() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        let remediate = client
+            .update_job_retries(7, 3)
+            .and_then(|_| client.resolve_incident(8));
+        futures::executor::block_on(remediate).unwrap();
+        let retries = mock.retries.lock().unwrap();
+        assert_eq!((retries[0].jobKey, retries[0].retries), (7, 3));
+        assert_eq!(mock.resolved.lock().unwrap()[0].incidentKey, 8);
+    }
+
+    fn partitions(roles: &[(i32, gateway::Partition_PartitionBrokerRole)]) -> gateway::BrokerInfo {
+        let mut broker = gateway::BrokerInfo::default();
+        for &(id, role) in roles {
+            let mut partition = gateway::Partition::default();
+            partition.set_partitionId(id);
+            partition.set_role(role);
+            broker.mut_partitions().push(partition);
+        }
+        broker
+    }
+
+    #[test]
+    fn healthy_when_every_partition_has_a_leader() {
+        use gateway::Partition_PartitionBrokerRole::{FOLLOWER, LEADER};
+        let mock = MockGateway::default();
+        let client = mock.client();
+        assert!(!futures::executor::block_on(client.healthy()));
+
+        {
+            let mut topology = mock.topology.lock().unwrap();
+            topology.set_partitionsCount(2);
+            topology
+                .mut_brokers()
+                .push(partitions(&[(1, LEADER), (2, FOLLOWER)]));
+            topology.mut_brokers().push(partitions(&[(1, FOLLOWER)]));
+        }
+        let topology = futures::executor::block_on(client.topology()).unwrap();
+        assert_eq!(topology.leader_partitions(), vec![1]);
+        assert!(!topology.is_healthy());
+
+        mock.topology.lock().unwrap().mut_brokers()[1] = partitions(&[(1, FOLLOWER), (2, LEADER)]);
+        assert!(futures::executor::block_on(client.healthy()));
+    }
+
+    #[test]
+    fn retries_commands_rejected_by_backpressure() {
+        let mock = MockGateway::default();
+        *mock.backpressure.lock().unwrap() = 2;
+        let retries = Arc::new(Mutex::new(Vec::new()));
+        let recorded = retries.clone();
+        let mut client = mock.client();
+        client.backpressure_retry = BackpressureRetry::new()
+            .initial_delay(Duration::from_millis(1))
+            .on_backpressure(move |retry, _| recorded.lock().unwrap().push(retry));
+        futures::executor::block_on(client.complete_job(CompleteJob::new(1, None))).unwrap();
+        assert_eq!(*retries.lock().unwrap(), vec![1, 2]);
+        assert_eq!(mock.completed.lock().unwrap().len(), 1);
+
+        *mock.backpressure.lock().unwrap() = 3;
+        client.backpressure_retry = client.backpressure_retry.max_retries(1);
+        let result = futures::executor::block_on(client.complete_job(CompleteJob::new(2, None)));
+        assert!(result.unwrap_err().is_backpressure());
+        assert_eq!(*mock.backpressure.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn retries_only_messages_with_an_id() {
+        let mock = MockGateway::default();
+        let mut client = mock.client();
+        client.retry_policy = Some(RetryPolicy::new().initial_delay(Duration::from_millis(1)));
+
+        *mock.unavailable.lock().unwrap() = 2;
+        let message = PublishMessage::with_correlation_key("payment-confirmed", "order-10");
+        futures::executor::block_on(client.publish_message(message.message_id("payment-10")))
+            .unwrap();
+        assert_eq!(mock.published.lock().unwrap().len(), 3);
+
+        *mock.unavailable.lock().unwrap() = 1;
+        let message = PublishMessage::with_correlation_key("payment-confirmed", "order-10");
+        let result = futures::executor::block_on(client.publish_message(message));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Unavailable);
+        assert_eq!(mock.published.lock().unwrap().len(), 4);
+    }
+
+    fn status(code: i32) -> Error {
+        Error::CompleteJobError(grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+            grpc_status: code,
+            grpc_message: "rejected".to_string(),
+        }))
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert_eq!(status(5).kind(), ErrorKind::NotFound);
+        assert_eq!(status(3).kind(), ErrorKind::InvalidArgument);
+        assert_eq!(status(8).kind(), ErrorKind::Backpressure);
+        assert_eq!(status(2).kind(), ErrorKind::Other);
+        assert_eq!(status(5).grpc_status(), Some((5, "rejected")));
+        let io = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            Error::GatewayError(grpc::Error::Io(io)).kind(),
+            ErrorKind::Connection
+        );
+        assert_eq!(
+            Error::VariablesNotAnObject("array".to_string()).kind(),
+            ErrorKind::InvalidArgument
+        );
+        assert_eq!(Error::DeadlineExceeded.grpc_status(), None);
+    }
+
+    #[test]
+    fn interceptors_add_metadata_after_credentials() {
+        let mock = MockGateway::default();
+        let client = mock
+            .client()
+            .with_credentials(StaticCredentials)
+            .with_interceptor(|metadata: &mut RequestMetadata| metadata.add("x-tenant", "acme"))
+            .with_interceptor(|metadata: &mut RequestMetadata| {
+                let authorized = metadata.get("authorization").is_some();
+                metadata.add("x-authorized", authorized.to_string());
+            });
+        futures::executor::block_on(client.complete_job(CompleteJob::new(1, None))).unwrap();
+        let metadata = mock.metadata.lock().unwrap();
+        assert_eq!(metadata[0].get("x-tenant"), Some(&b"acme"[..]));
+        assert_eq!(metadata[0].get("x-authorized"), Some(&b"true"[..]));
+    }
+
+    #[test]
+    fn client_is_usable_through_the_trait() {
+        let mock = MockGateway::default();
+        let client = mock.client();
+        complete_all(&client, &[1]);
+        let boxed: Box<dyn ZeebeGateway> = Box::new(client);
+        complete_all(boxed.as_ref(), &[2]);
+        assert_eq!(mock.completed_keys(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::gateway_grpc::{Gateway, GatewayClient};
+use crate::reconnect::{Connection, ConnectionPool};
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::{
+    BackpressureRetry, Client, CredentialsProvider, Error, Interceptor, ReconnectPolicy,
+    RetryPolicy,
+};
+use grpc::ClientStubExt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures the connection of a `Client`. Create one with `Client::builder`.
+///
+/// The transport does not support HTTP/2 keep-alive pings or message size limits, so those
+/// cannot be configured.
+#[derive(Clone)]
+pub struct ClientBuilder {
+    host: String,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    no_delay: Option<bool>,
+    credentials: Option<Arc<dyn CredentialsProvider + Send + Sync>>,
+    backpressure_retry: BackpressureRetry,
+    retry_policy: Option<RetryPolicy>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    connections: usize,
+    interceptors: Vec<Arc<dyn Interceptor + Send + Sync>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<crate::Metrics>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<TlsConfig>,
+}
+
+impl ClientBuilder {
+    pub fn new<S: Into<String>>(host: S, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            connect_timeout: None,
+            request_timeout: None,
+            no_delay: None,
+            credentials: None,
+            backpressure_retry: BackpressureRetry::default(),
+            retry_policy: None,
+            reconnect_policy: Some(ReconnectPolicy::default()),
+            connections: 1,
+            interceptors: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+        }
+    }
+
+    /// Configure a client for the gateway at `address`, given as `host:port`, or as `[host]:port`
+    /// for an IPv6 address like `[::1]:26500`. The port defaults to 26500. Fails with
+    /// `Error::ConfigurationError` if the address is malformed or the port is not a port number.
+    pub fn from_address(address: &str) -> Result<Self, Error> {
+        let invalid = || {
+            Error::ConfigurationError(format!(
+                "invalid gateway address {}, expected host:port",
+                address
+            ))
+        };
+        let (host, port) = if address.starts_with('[') {
+            let end = address.find(']').ok_or_else(invalid)?;
+            let port = match &address[end + 1..] {
+                "" => "26500",
+                rest if rest.starts_with(':') => &rest[1..],
+                _ => return Err(invalid()),
+            };
+            (&address[1..end], port)
+        } else {
+            match address.rfind(':') {
+                // an IPv6 address must be bracketed to tell it apart from its port
+                Some(colon) if address[..colon].contains(':') => return Err(invalid()),
+                Some(colon) => (&address[..colon], &address[colon + 1..]),
+                None => (address, "26500"),
+            }
+        };
+        let port = port.parse().map_err(|_| invalid())?;
+        Ok(Self::new(host, port))
+    }
+
+    /// Configure the client like `zbctl` and the other zeebe clients, from the environment:
+    ///
+    /// | variable | |
+    /// |---|---|
+    /// | `ZEEBE_ADDRESS` | the gateway as `host:port` or `[host]:port`, defaults to `127.0.0.1:26500` |
+    /// | `ZEEBE_CLIENT_ID`, `ZEEBE_CLIENT_SECRET` | OAuth client credentials, needs the `oauth` feature |
+    /// | `ZEEBE_AUTHORIZATION_SERVER_URL` | the token endpoint, defaults to Camunda Cloud's |
+    /// | `ZEEBE_TOKEN_AUDIENCE` | the token audience, defaults to the gateway host |
+    /// | `ZEEBE_CA_CERTIFICATE_PATH` | a PEM root certificate to trust, needs the `tls` feature |
+    /// | `ZEEBE_INSECURE_CONNECTION` | `true` to connect without TLS |
+    ///
+    /// Like `zbctl`, the client connects over TLS when credentials or a certificate are given,
+    /// unless `ZEEBE_INSECURE_CONNECTION` is `true`. Fails with `Error::ConfigurationError` if a
+    /// variable is invalid or needs a feature that is not enabled.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Result<Self, Error> {
+        let address = var("ZEEBE_ADDRESS").unwrap_or_else(|| "127.0.0.1:26500".to_string());
+        #[allow(unused_mut)]
+        let mut builder = Self::from_address(&address)?;
+
+        let insecure = var("ZEEBE_INSECURE_CONNECTION").map_or(false, |v| v == "true");
+        let credentials = match (var("ZEEBE_CLIENT_ID"), var("ZEEBE_CLIENT_SECRET")) {
+            (Some(client_id), Some(client_secret)) => Some((client_id, client_secret)),
+            (None, None) => None,
+            _ => {
+                return Err(Error::ConfigurationError(
+                    "ZEEBE_CLIENT_ID and ZEEBE_CLIENT_SECRET must be set together".to_string(),
+                ))
+            }
+        };
+        let ca_certificate_path = var("ZEEBE_CA_CERTIFICATE_PATH");
+
+        if let Some((_client_id, _client_secret)) = credentials.as_ref() {
+            #[cfg(feature = "oauth")]
+            {
+                let authorization_server_url = var("ZEEBE_AUTHORIZATION_SERVER_URL")
+                    .unwrap_or_else(|| "https://login.cloud.camunda.io/oauth/token".to_string());
+                let audience = var("ZEEBE_TOKEN_AUDIENCE").unwrap_or_else(|| builder.host.clone());
+                builder = builder.credentials(crate::OAuthCredentialsProvider::new(
+                    _client_id.as_str(),
+                    _client_secret.as_str(),
+                    audience,
+                    authorization_server_url,
+                ));
+            }
+            #[cfg(not(feature = "oauth"))]
+            return Err(Error::ConfigurationError(
+                "ZEEBE_CLIENT_ID is set, but the oauth feature is not enabled".to_string(),
+            ));
+        }
+
+        if !insecure && (credentials.is_some() || ca_certificate_path.is_some()) {
+            #[cfg(feature = "tls")]
+            {
+                let mut tls_config = TlsConfig::new();
+                if let Some(path) = ca_certificate_path {
+                    let pem = std::fs::read(&path).map_err(|e| {
+                        Error::ConfigurationError(format!(
+                            "cannot read ZEEBE_CA_CERTIFICATE_PATH {}: {}",
+                            path, e
+                        ))
+                    })?;
+                    tls_config = tls_config.root_certificate_pem(pem);
+                }
+                builder = builder.tls(tls_config);
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err(Error::ConfigurationError(
+                "the connection needs TLS, but the tls feature is not enabled".to_string(),
+            ));
+        }
+        Ok(builder)
+    }
+
+    /// How long to wait for the connection to the gateway to be established.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// The deadline for every unary call. A call that takes longer fails with
+    /// `Error::DeadlineExceeded`. Job activation streams are not bounded by this deadline. Override
+    /// it for single calls with `Client::with_request_timeout`.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Set `TCP_NODELAY` on the connection.
+    pub fn no_delay(mut self, no_delay: bool) -> Self {
+        self.no_delay = Some(no_delay);
+        self
+    }
+
+    /// Attach the credentials of `credentials_provider` to every call.
+    pub fn credentials<C: CredentialsProvider + Send + Sync + 'static>(
+        mut self,
+        credentials_provider: C,
+    ) -> Self {
+        self.credentials = Some(Arc::new(credentials_provider));
+        self
+    }
+
+    /// Run `interceptor` on the metadata of every call, see `Client::with_interceptor`.
+    pub fn interceptor<I: Interceptor + Send + Sync + 'static>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Record metrics of the calls of the client and of the workers that use it.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: crate::Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Retry idempotent commands that fail for a transient reason, see `RetryPolicy`. By default
+    /// they are not retried.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// How commands rejected because of backpressure are retried. Pass
+    /// `BackpressureRetry::disabled()` to fail such calls right away.
+    pub fn backpressure_retry(mut self, backpressure_retry: BackpressureRetry) -> Self {
+        self.backpressure_retry = backpressure_retry;
+        self
+    }
+
+    /// How often the client reconnects while the gateway is unreachable.
+    pub fn reconnect_policy(mut self, reconnect_policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(reconnect_policy);
+        self
+    }
+
+    /// Never open a new connection; once the connection fails every call fails.
+    pub fn without_reconnect(mut self) -> Self {
+        self.reconnect_policy = None;
+        self
+    }
+
+    /// Open `connections` connections to the gateway and let calls take turns on them. A single
+    /// HTTP/2 connection already multiplexes any number of concurrent calls, and every clone of
+    /// the client shares it, so a pool only helps clients with very high request rates. Defaults
+    /// to one connection; zero is treated as one.
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.connections = std::cmp::max(connections, 1);
+        self
+    }
+
+    /// Connect to the gateway over TLS.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls_config: TlsConfig) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    fn client_conf(&self) -> grpc::ClientConf {
+        let mut conf = grpc::ClientConf::default();
+        conf.http.connection_timeout = self.connect_timeout;
+        conf.http.no_delay = self.no_delay;
+        conf
+    }
+
+    fn gateway_client(&self) -> Result<GatewayClient, Error> {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls_config) = &self.tls_config {
+                return tls_config.gateway_client(&self.host, self.port, self.client_conf());
+            }
+        }
+        GatewayClient::new_plain(&self.host, self.port, self.client_conf())
+            .map_err(|e| Error::GatewayError(e))
+    }
+
+    /// Connect to the gateway and build the `Client`.
+    pub fn build(self) -> Result<Client, Error> {
+        let gateway_client: Arc<dyn Gateway + Send + Sync> = Arc::new(self.gateway_client()?);
+        let mut client = Client::from_gateway_client(gateway_client.clone());
+        if self.reconnect_policy.is_some() || self.connections > 1 {
+            let mut connections = Vec::with_capacity(self.connections);
+            let mut first = Some(gateway_client);
+            for _ in 0..self.connections {
+                let gateway_client: Arc<dyn Gateway + Send + Sync> = match first.take() {
+                    Some(gateway_client) => gateway_client,
+                    None => Arc::new(self.gateway_client()?),
+                };
+                let builder = self.clone();
+                connections.push(Connection::new(
+                    gateway_client,
+                    self.reconnect_policy.clone(),
+                    move || {
+                        Ok(Arc::new(builder.gateway_client()?) as Arc<dyn Gateway + Send + Sync>)
+                    },
+                ));
+            }
+            client.connections = Some(Arc::new(ConnectionPool::new(connections)));
+        }
+        client.credentials = self.credentials;
+        client.request_timeout = self.request_timeout;
+        client.backpressure_retry = self.backpressure_retry;
+        client.retry_policy = self.retry_policy;
+        client.interceptors = self.interceptors;
+        #[cfg(feature = "metrics")]
+        {
+            client.metrics = self.metrics;
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ClientBuilder, Error};
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ClientBuilder, Error> {
+        let vars: HashMap<_, _> = vars.iter().cloned().collect();
+        ClientBuilder::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    fn is_configuration_error(result: Result<ClientBuilder, Error>) -> bool {
+        match result {
+            Err(Error::ConfigurationError(_)) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn reads_the_gateway_address() {
+        let builder = from_vars(&[("ZEEBE_ADDRESS", "zeebe.example.com:443")]).unwrap();
+        assert_eq!(
+            (builder.host.as_str(), builder.port),
+            ("zeebe.example.com", 443)
+        );
+        let builder = from_vars(&[]).unwrap();
+        assert_eq!((builder.host.as_str(), builder.port), ("127.0.0.1", 26500));
+
+        assert!(is_configuration_error(from_vars(&[(
+            "ZEEBE_ADDRESS",
+            "zeebe:gateway"
+        )])));
+        let builder = ClientBuilder::from_address("zeebe").unwrap();
+        assert_eq!((builder.host.as_str(), builder.port), ("zeebe", 26500));
+
+        let builder = from_vars(&[("ZEEBE_ADDRESS", "[::1]:26501")]).unwrap();
+        assert_eq!((builder.host.as_str(), builder.port), ("::1", 26501));
+        let builder = ClientBuilder::from_address("[::1]").unwrap();
+        assert_eq!((builder.host.as_str(), builder.port), ("::1", 26500));
+        for address in &["[::1", "[::1]26500", "::1:26500"] {
+            assert!(is_configuration_error(ClientBuilder::from_address(address)));
+        }
+        assert!(is_configuration_error(from_vars(&[(
+            "ZEEBE_CLIENT_ID",
+            "zeebe"
+        )])));
+    }
+}
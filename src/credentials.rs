@@ -0,0 +1,250 @@
+use crate::Error;
+use futures::Future;
+use std::pin::Pin;
+
+/// Supplies the credentials that are attached to every gateway call. The `authorization` future
+/// resolves to the value of the `authorization` header, e.g. `Bearer <token>`. Providers are
+/// called once per call and should cache their credentials.
+pub trait CredentialsProvider {
+    fn authorization(&self) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>;
+}
+
+#[cfg(feature = "oauth")]
+pub use oauth::OAuthCredentialsProvider;
+
+#[cfg(feature = "oauth")]
+mod oauth {
+    use crate::{CredentialsProvider, Error};
+    use futures::compat::Future01CompatExt;
+    use futures::future::Shared;
+    use futures::{Future, FutureExt, TryFutureExt};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Tokens are refreshed this long before they expire.
+    const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+    #[derive(Clone, Debug)]
+    struct CachedToken {
+        authorization: String,
+        refresh_at: Instant,
+    }
+
+    impl CachedToken {
+        fn new(access_token: &str, expires_in: Duration, now: Instant) -> Self {
+            let margin = std::cmp::min(REFRESH_MARGIN, expires_in / 2);
+            Self {
+                authorization: format!("Bearer {}", access_token),
+                refresh_at: now + (expires_in - margin),
+            }
+        }
+
+        fn is_fresh(&self, now: Instant) -> bool {
+            now < self.refresh_at
+        }
+    }
+
+    /// A token fetch, shared by every call that waits for it. Fails with the error message.
+    type Refresh = Shared<Pin<Box<dyn Future<Output = Result<CachedToken, String>> + Send>>>;
+
+    enum TokenState {
+        Missing,
+        Cached(CachedToken),
+        Refreshing(Refresh),
+    }
+
+    /// Caches a token and refreshes it once it is stale. Only one refresh is in flight at a time:
+    /// calls that need a token while it is being refreshed wait for that refresh instead of
+    /// fetching one of their own.
+    #[derive(Clone)]
+    struct TokenCache {
+        state: Arc<Mutex<TokenState>>,
+    }
+
+    impl TokenCache {
+        fn new() -> Self {
+            Self {
+                state: Arc::new(Mutex::new(TokenState::Missing)),
+            }
+        }
+
+        /// The authorization of the cached token, or of a token fetched with `fetch` if it is
+        /// stale and nobody is refreshing it yet.
+        fn authorization<F, R>(
+            &self,
+            fetch: F,
+        ) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>
+        where
+            F: FnOnce() -> R,
+            R: Future<Output = Result<CachedToken, String>> + Send + 'static,
+        {
+            let mut state = self.state.lock().unwrap();
+            let refresh = match &*state {
+                TokenState::Cached(token) if token.is_fresh(Instant::now()) => {
+                    return futures::future::ok(token.authorization.clone()).boxed();
+                }
+                TokenState::Refreshing(refresh) => refresh.clone(),
+                _ => {
+                    let cache = self.state.clone();
+                    let refresh = fetch()
+                        .map(move |fetched| {
+                            // a failed refresh is retried by the next call
+                            *cache.lock().unwrap() = match &fetched {
+                                Ok(token) => TokenState::Cached(token.clone()),
+                                Err(_) => TokenState::Missing,
+                            };
+                            fetched
+                        })
+                        .boxed()
+                        .shared();
+                    *state = TokenState::Refreshing(refresh.clone());
+                    refresh
+                }
+            };
+            refresh
+                .map_ok(|token| token.authorization)
+                .map_err(Error::CredentialsError)
+                .boxed()
+        }
+    }
+
+    /// Fetches access tokens with the OAuth client credentials flow, e.g. for Camunda Cloud. The
+    /// token is cached and refreshed shortly before it expires, once for all the calls that need it
+    /// at that time.
+    #[derive(Clone)]
+    pub struct OAuthCredentialsProvider {
+        client_id: String,
+        client_secret: String,
+        audience: String,
+        authorization_server_url: String,
+        http_client: reqwest::r#async::Client,
+        token: TokenCache,
+    }
+
+    impl OAuthCredentialsProvider {
+        pub fn new<S1: Into<String>, S2: Into<String>, S3: Into<String>, S4: Into<String>>(
+            client_id: S1,
+            client_secret: S2,
+            audience: S3,
+            authorization_server_url: S4,
+        ) -> Self {
+            Self {
+                client_id: client_id.into(),
+                client_secret: client_secret.into(),
+                audience: audience.into(),
+                authorization_server_url: authorization_server_url.into(),
+                http_client: reqwest::r#async::Client::new(),
+                token: TokenCache::new(),
+            }
+        }
+
+        fn fetch_token(&self) -> impl Future<Output = Result<CachedToken, String>> + Send {
+            let params = [
+                ("grant_type", "client_credentials"),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("audience", self.audience.as_str()),
+            ];
+            self.http_client
+                .post(self.authorization_server_url.as_str())
+                .form(&params)
+                .send()
+                .compat()
+                .and_then(|response| {
+                    futures::future::ready(response.error_for_status())
+                        .and_then(|mut response| response.json::<serde_json::Value>().compat())
+                })
+                .map_err(|e| e.to_string())
+                .and_then(|body| {
+                    let access_token = body["access_token"].as_str();
+                    let expires_in = body["expires_in"].as_u64();
+                    futures::future::ready(match (access_token, expires_in) {
+                        (Some(access_token), Some(expires_in)) => Ok(CachedToken::new(
+                            access_token,
+                            Duration::from_secs(expires_in),
+                            Instant::now(),
+                        )),
+                        _ => Err(format!("unexpected token response: {}", body)),
+                    })
+                })
+        }
+    }
+
+    impl CredentialsProvider for OAuthCredentialsProvider {
+        fn authorization(&self) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>> {
+            self.token.authorization(|| self.fetch_token())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{CachedToken, TokenCache};
+        use futures::FutureExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        #[test]
+        fn refreshes_before_expiry() {
+            let now = Instant::now();
+            let token = CachedToken::new("abc", Duration::from_secs(3600), now);
+            assert_eq!(token.authorization, "Bearer abc");
+            assert!(token.is_fresh(now + Duration::from_secs(3500)));
+            assert!(!token.is_fresh(now + Duration::from_secs(3580)));
+        }
+
+        #[test]
+        fn short_lived_tokens_are_refreshed_at_half_life() {
+            let now = Instant::now();
+            let token = CachedToken::new("abc", Duration::from_secs(20), now);
+            assert!(token.is_fresh(now + Duration::from_secs(9)));
+            assert!(!token.is_fresh(now + Duration::from_secs(10)));
+        }
+
+        #[test]
+        fn concurrent_calls_share_one_refresh() {
+            let cache = TokenCache::new();
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let (send_token, token) = futures::channel::oneshot::channel::<&str>();
+            let token = token.shared();
+            let fetch = || {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                let token = token.clone();
+                token.map(|access_token| {
+                    let access_token = access_token.unwrap();
+                    Ok(CachedToken::new(
+                        access_token,
+                        Duration::from_secs(3600),
+                        Instant::now(),
+                    ))
+                })
+            };
+            let first = cache.authorization(fetch);
+            let second = cache.authorization(fetch);
+            send_token.send("abc").unwrap();
+            let (first, second) = futures::executor::block_on(futures::future::join(first, second));
+            assert_eq!(first.unwrap(), "Bearer abc");
+            assert_eq!(second.unwrap(), "Bearer abc");
+            // the fresh token is served from the cache
+            let third = futures::executor::block_on(cache.authorization(fetch));
+            assert_eq!(third.unwrap(), "Bearer abc");
+            assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        }
+
+        #[test]
+        fn failed_refreshes_are_retried_by_the_next_call() {
+            let cache = TokenCache::new();
+            let failed = cache.authorization(|| futures::future::err("unauthorized".to_string()));
+            assert!(futures::executor::block_on(failed).is_err());
+            let refreshed = cache.authorization(|| {
+                let token = CachedToken::new("abc", Duration::from_secs(3600), Instant::now());
+                futures::future::ok(token)
+            });
+            assert_eq!(
+                futures::executor::block_on(refreshed).unwrap(),
+                "Bearer abc"
+            );
+        }
+    }
+}
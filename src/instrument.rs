@@ -0,0 +1,118 @@
+//! Spans for gateway calls and jobs when the `tracing` feature is enabled. Without the feature
+//! the futures and streams are passed through unchanged.
+use crate::ActivatedJob;
+#[cfg(feature = "tracing")]
+use futures::task::{Context, Poll};
+use futures::{Future, Stream};
+#[cfg(feature = "tracing")]
+use futures::{FutureExt, StreamExt};
+use std::pin::Pin;
+#[cfg(feature = "tracing")]
+use tracing::Span;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type BoxStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+
+/// A future or stream that enters `span` every time it is polled, so that everything it does is
+/// recorded in the span.
+#[cfg(feature = "tracing")]
+pub(crate) struct Instrumented<T> {
+    inner: Pin<Box<T>>,
+    span: Span,
+}
+
+#[cfg(feature = "tracing")]
+impl<T: ?Sized> Instrumented<T> {
+    pub fn new(inner: Pin<Box<T>>, span: Span) -> Self {
+        Self { inner, span }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T: Future + ?Sized> Future for Instrumented<T> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T::Output> {
+        let this = self.get_mut();
+        let _entered = this.span.enter();
+        this.inner.as_mut().poll(cx)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T: Stream + ?Sized> Stream for Instrumented<T> {
+    type Item = T::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T::Item>> {
+        let this = self.get_mut();
+        let _entered = this.span.enter();
+        this.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// The name of a gateway call for spans, e.g. `CompleteJob` for a `CompleteJobRequest`.
+#[cfg(feature = "tracing")]
+pub(crate) fn rpc_name<Req>() -> &'static str {
+    let type_name = std::any::type_name::<Req>();
+    let name = type_name.rsplit("::").next().unwrap_or(type_name);
+    name.trim_end_matches("Request")
+}
+
+/// Record a unary call in a `zeebe_rpc` span.
+#[cfg(feature = "tracing")]
+pub(crate) fn rpc_future<Req, T: 'static>(call: BoxFuture<T>) -> BoxFuture<T> {
+    let span = tracing::debug_span!("zeebe_rpc", rpc = rpc_name::<Req>());
+    Instrumented::new(call, span).boxed()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn rpc_future<Req, T: 'static>(call: BoxFuture<T>) -> BoxFuture<T> {
+    call
+}
+
+/// Record a streaming call in a `zeebe_rpc` span.
+#[cfg(feature = "tracing")]
+pub(crate) fn rpc_stream<Req, T: 'static>(call: BoxStream<T>) -> BoxStream<T> {
+    let span = tracing::debug_span!("zeebe_rpc", rpc = rpc_name::<Req>());
+    Instrumented::new(call, span).boxed()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn rpc_stream<Req, T: 'static>(call: BoxStream<T>) -> BoxStream<T> {
+    call
+}
+
+/// Record handling and reporting `job` in a `zeebe_job` span.
+#[cfg(feature = "tracing")]
+pub(crate) fn job_future<T: 'static>(job: &ActivatedJob, future: BoxFuture<T>) -> BoxFuture<T> {
+    let span = tracing::info_span!(
+        "zeebe_job",
+        job_key = job.key,
+        job_type = %job.field_type,
+        workflow_instance_key = job.workflow_instance_key,
+        bpmn_process_id = %job.bpmn_process_id,
+        element_id = %job.element_id,
+        worker = %job.worker,
+        retries = job.retries
+    );
+    Instrumented::new(future, span).boxed()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn job_future<T: 'static>(_job: &ActivatedJob, future: BoxFuture<T>) -> BoxFuture<T> {
+    future
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use crate::gateway;
+
+    #[test]
+    fn names_calls_after_their_request() {
+        assert_eq!(
+            super::rpc_name::<gateway::CompleteJobRequest>(),
+            "CompleteJob"
+        );
+        assert_eq!(super::rpc_name::<gateway::TopologyRequest>(), "Topology");
+    }
+}
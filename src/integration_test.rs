@@ -0,0 +1,201 @@
+//! Run end-to-end tests of workers against a real broker. `ZeebeContainer` starts a zeebe broker
+//! in Docker with [testcontainers](https://crates.io/crates/testcontainers), waits until its
+//! topology is healthy and hands out clients connected to it. Enable the `integration-test`
+//! feature in the dev-dependencies of your crate to use it; Docker must be running.
+//!
+//! ```rust,ignore
+//! let docker = testcontainers::clients::Cli::default();
+//! let zeebe = ZeebeContainer::start(&docker).unwrap();
+//! let client = zeebe.client();
+//! block_on(zeebe.deploy_fixture("tests/fixtures/order-process.bpmn")).unwrap();
+//! let instance = block_on(client.create_workflow_instance(order)).unwrap();
+//! // ... run the workers under test ...
+//! block_on(zeebe.wait_until(Duration::from_secs(30), move || {
+//!     futures::future::ok(!shipped.lock().unwrap().is_empty()).boxed()
+//! }))
+//! .unwrap();
+//! ```
+//!
+//! The 0.20 gateway cannot query workflow instances, so the helpers cannot tell how an instance
+//! ended. Wait for the expected outcome with `wait_until` where it matters; `wait_until_ended`
+//! only knows that an instance is gone.
+use crate::{Client, DeployedWorkflows, Error, ErrorKind};
+use futures::{Future, FutureExt};
+use std::path::Path;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use testcontainers::clients::Cli;
+use testcontainers::images::generic::GenericImage;
+use testcontainers::{Container, Docker};
+
+/// The image `ZeebeContainer::start` runs, matching the gateway protocol of this crate.
+pub const ZEEBE_IMAGE: &str = "camunda/zeebe:0.20.1";
+
+/// The gateway port inside the container.
+const GATEWAY_PORT: u32 = 26500;
+
+/// How often the helpers poll the broker while they wait.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A zeebe broker running in a Docker container. The container is removed when this is dropped.
+pub struct ZeebeContainer<'d> {
+    container: Container<'d, Cli, GenericImage>,
+    client: Client,
+}
+
+impl<'d> ZeebeContainer<'d> {
+    /// Start `ZEEBE_IMAGE` and wait up to a minute for the broker to be ready.
+    pub fn start(docker: &'d Cli) -> Result<Self, Error> {
+        Self::start_image(docker, ZEEBE_IMAGE, Duration::from_secs(60))
+    }
+
+    /// Start a zeebe `image` and wait up to `timeout` for every partition to have a leader.
+    pub fn start_image(docker: &'d Cli, image: &str, timeout: Duration) -> Result<Self, Error> {
+        let container = docker.run(GenericImage::new(image));
+        let port = container.get_host_port(GATEWAY_PORT).ok_or_else(|| {
+            Error::ConfigurationError(format!("the container does not expose {}", GATEWAY_PORT))
+        })?;
+        let client = Client::new("localhost", port as u16)?;
+        let zeebe_container = Self { container, client };
+        futures::executor::block_on(zeebe_container.wait_until_ready(timeout))?;
+        Ok(zeebe_container)
+    }
+
+    /// A client connected to the broker.
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// The port on the host that the gateway is published on.
+    pub fn gateway_port(&self) -> u16 {
+        self.container
+            .get_host_port(GATEWAY_PORT)
+            .expect("the gateway port is published") as u16
+    }
+
+    /// Resolve once the topology is healthy, or fail with `Error::DeadlineExceeded` after
+    /// `timeout`.
+    pub fn wait_until_ready(
+        &self,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let client = self.client();
+        poll_until(Instant::now() + timeout, POLL_INTERVAL, move || {
+            client.healthy().map(Ok).boxed()
+        })
+    }
+
+    /// Deploy a workflow file, e.g. a BPMN fixture of the tests.
+    pub fn deploy_fixture<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Output = Result<DeployedWorkflows, Error>> + Send {
+        self.client.deploy_from_path(path)
+    }
+
+    /// Resolve once `probe` resolves to `true`, or fail with `Error::DeadlineExceeded` after
+    /// `timeout`. The probe checks the expected outcome of a test, e.g. that the workers under
+    /// test handled the last job of a workflow or wrote its result somewhere.
+    pub fn wait_until<F>(
+        &self,
+        timeout: Duration,
+        probe: F,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>
+    where
+        F: Fn() -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send>> + Send + 'static,
+    {
+        poll_until(Instant::now() + timeout, POLL_INTERVAL, probe)
+    }
+
+    /// Resolve once the workflow instance no longer exists, or fail with
+    /// `Error::DeadlineExceeded` after `timeout`.
+    ///
+    /// The instance is probed by setting an empty document of variables on it, which the
+    /// gateway rejects as not found once the instance is gone. This has two catches:
+    ///
+    /// - completed, cancelled and terminated instances look alike, and so does a key that never
+    ///   belonged to an instance, which resolves at once. Use `wait_until` to check the outcome.
+    /// - every probe writes a command to the log of the partition of the instance. It changes no
+    ///   variables, but it shows up in exporters and in the history of the instance.
+    pub fn wait_until_ended(
+        &self,
+        workflow_instance_key: i64,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let client = self.client();
+        poll_until(Instant::now() + timeout, POLL_INTERVAL, move || {
+            client
+                .set_variables_json(workflow_instance_key, "{}", true)
+                .map(|result| match result {
+                    Ok(()) => Ok(false),
+                    Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(true),
+                    Err(e) => Err(e),
+                })
+                .boxed()
+        })
+    }
+}
+
+/// Call `probe` every `interval` until it resolves to `true`, fails, or `deadline` passes.
+fn poll_until<F>(
+    deadline: Instant,
+    interval: Duration,
+    probe: F,
+) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>
+where
+    F: Fn() -> Pin<Box<dyn Future<Output = Result<bool, Error>> + Send>> + Send + 'static,
+{
+    probe()
+        .then(move |result| match result {
+            Ok(true) => futures::future::ok(()).left_future(),
+            Ok(false) if Instant::now() >= deadline => {
+                futures::future::err(Error::DeadlineExceeded).left_future()
+            }
+            Ok(false) => futures_timer::Delay::new(interval)
+                .then(move |_| poll_until(deadline, interval, probe))
+                .right_future(),
+            Err(e) => futures::future::err(e).left_future(),
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::poll_until;
+    use crate::Error;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn polls_until_the_probe_succeeds_or_the_deadline_passes() {
+        let probes = Arc::new(AtomicUsize::new(0));
+        let counted = probes.clone();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let polled = poll_until(deadline, Duration::from_millis(1), move || {
+            let probe = counted.fetch_add(1, Ordering::SeqCst) + 1;
+            futures::future::ok(probe == 3).boxed()
+        });
+        assert!(futures::executor::block_on(polled).is_ok());
+        assert_eq!(probes.load(Ordering::SeqCst), 3);
+
+        let deadline = Instant::now() + Duration::from_millis(5);
+        let polled = poll_until(deadline, Duration::from_millis(1), || {
+            futures::future::ok(false).boxed()
+        });
+        match futures::executor::block_on(polled) {
+            Err(Error::DeadlineExceeded) => {}
+            other => panic!("expected the deadline to pass, got {:?}", other),
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let polled = poll_until(deadline, Duration::from_millis(1), || {
+            futures::future::err(Error::JobError("broken probe".to_string())).boxed()
+        });
+        match futures::executor::block_on(polled) {
+            Err(Error::JobError(message)) => assert_eq!(message, "broken probe"),
+            other => panic!("expected the probe error, got {:?}", other),
+        }
+    }
+}
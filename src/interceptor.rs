@@ -0,0 +1,44 @@
+/// Mutates the metadata of every gateway call before it is sent, e.g. to add tenant routing
+/// headers or trace ids. Closures taking `&mut RequestMetadata` implement this trait.
+///
+/// Interceptors run after the credentials are attached, in the order they were added.
+pub trait Interceptor {
+    fn intercept(&self, metadata: &mut RequestMetadata);
+}
+
+impl<F: Fn(&mut RequestMetadata)> Interceptor for F {
+    fn intercept(&self, metadata: &mut RequestMetadata) {
+        self(metadata)
+    }
+}
+
+/// The gRPC metadata of an outgoing gateway call.
+pub struct RequestMetadata<'a> {
+    metadata: &'a mut grpc::Metadata,
+}
+
+impl<'a> RequestMetadata<'a> {
+    pub(crate) fn new(metadata: &'a mut grpc::Metadata) -> Self {
+        Self { metadata }
+    }
+
+    /// Add a metadata entry. Keys must be lowercase.
+    pub fn add<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.metadata.add(
+            grpc::MetadataKey::from(key.into()),
+            bytes::Bytes::from(value.into()),
+        );
+    }
+
+    /// The value of the first entry for `key`, if it is valid UTF-8.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .get(key)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// The underlying metadata, e.g. to add binary entries.
+    pub fn metadata_mut(&mut self) -> &mut grpc::Metadata {
+        self.metadata
+    }
+}
@@ -8,9 +8,37 @@
 
 #[macro_use]
 extern crate failure;
+mod backpressure;
 mod client;
+mod client_builder;
+mod credentials;
 mod gateway;
 mod gateway_grpc;
+mod instrument;
+#[cfg(feature = "integration-test")]
+pub mod integration_test;
+mod interceptor;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(test)]
+mod mock_gateway;
+mod reconnect;
+mod retry;
+mod tenant;
+pub mod testing;
+#[cfg(feature = "tls")]
+mod tls;
 mod worker;
+pub use backpressure::BackpressureRetry;
 pub use client::*;
+pub use client_builder::ClientBuilder;
+pub use credentials::*;
+pub use interceptor::{Interceptor, RequestMetadata};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use reconnect::ReconnectPolicy;
+pub use retry::RetryPolicy;
+pub use tenant::TenantRouter;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
 pub use worker::*;
@@ -0,0 +1,142 @@
+use crate::{Error, JobResult};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use std::time::Duration;
+
+/// Prometheus metrics of a client and its workers. Create them once, register them with the
+/// registry that is exported by your service, and pass them to `ClientBuilder::metrics`. Every
+/// worker built from the client records into the same metrics.
+///
+/// | metric | labels |
+/// |---|---|
+/// | `zeebest_jobs_activated_total` | `job_type` |
+/// | `zeebest_jobs_completed_total` | `job_type` |
+/// | `zeebest_jobs_failed_total` | `job_type` |
+/// | `zeebest_job_handler_duration_seconds` | `job_type` |
+/// | `zeebest_activation_duration_seconds` | `job_type` |
+/// | `zeebest_grpc_errors_total` | `kind`, `code` |
+///
+/// `code` is the gRPC status code, or empty if the call failed without a status, e.g. because the
+/// connection failed.
+#[derive(Clone)]
+pub struct Metrics {
+    jobs_activated: IntCounterVec,
+    jobs_completed: IntCounterVec,
+    jobs_failed: IntCounterVec,
+    handler_duration: HistogramVec,
+    activation_duration: HistogramVec,
+    grpc_errors: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let job_counter =
+            |name: &str, help: &str| IntCounterVec::new(Opts::new(name, help), &["job_type"]);
+        let job_histogram = |name: &str, help: &str| {
+            HistogramVec::new(HistogramOpts::new(name, help), &["job_type"])
+        };
+        Ok(Self {
+            jobs_activated: job_counter("zeebest_jobs_activated_total", "Activated jobs.")?,
+            jobs_completed: job_counter("zeebest_jobs_completed_total", "Completed jobs.")?,
+            jobs_failed: job_counter(
+                "zeebest_jobs_failed_total",
+                "Jobs that failed or whose handler panicked.",
+            )?,
+            handler_duration: job_histogram(
+                "zeebest_job_handler_duration_seconds",
+                "How long the job handler took.",
+            )?,
+            activation_duration: job_histogram(
+                "zeebest_activation_duration_seconds",
+                "The round trip of a job activation.",
+            )?,
+            grpc_errors: IntCounterVec::new(
+                Opts::new("zeebest_grpc_errors_total", "Failed gateway calls."),
+                &["kind", "code"],
+            )?,
+        })
+    }
+
+    /// Register all metrics with `registry`.
+    pub fn register(&self, registry: &Registry) -> Result<(), prometheus::Error> {
+        registry.register(Box::new(self.jobs_activated.clone()))?;
+        registry.register(Box::new(self.jobs_completed.clone()))?;
+        registry.register(Box::new(self.jobs_failed.clone()))?;
+        registry.register(Box::new(self.handler_duration.clone()))?;
+        registry.register(Box::new(self.activation_duration.clone()))?;
+        registry.register(Box::new(self.grpc_errors.clone()))?;
+        Ok(())
+    }
+
+    pub(crate) fn jobs_activated(&self, job_type: &str, count: usize) {
+        self.jobs_activated
+            .with_label_values(&[job_type])
+            .inc_by(count as _);
+    }
+
+    /// Record the outcome of a job handler; `None` means the handler panicked.
+    pub(crate) fn job_handled(
+        &self,
+        job_type: &str,
+        job_result: Option<&JobResult>,
+        duration: Duration,
+    ) {
+        self.handler_duration
+            .with_label_values(&[job_type])
+            .observe(seconds(duration));
+        match job_result {
+            Some(JobResult::Complete { .. }) => {
+                self.jobs_completed.with_label_values(&[job_type]).inc()
+            }
+            Some(JobResult::Fail { .. }) | None => {
+                self.jobs_failed.with_label_values(&[job_type]).inc()
+            }
+            Some(JobResult::NoAction) => {}
+        }
+    }
+
+    pub(crate) fn activation_finished(&self, job_type: &str, duration: Duration) {
+        self.activation_duration
+            .with_label_values(&[job_type])
+            .observe(seconds(duration));
+    }
+
+    pub(crate) fn grpc_error(&self, error: &Error) {
+        let kind = format!("{:?}", error.kind());
+        let code = error
+            .grpc_status()
+            .map(|(code, _)| code.to_string())
+            .unwrap_or_default();
+        self.grpc_errors.with_label_values(&[&kind, &code]).inc();
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, JobResult, Metrics};
+    use std::time::Duration;
+
+    #[test]
+    fn records_job_outcomes_and_errors() {
+        let metrics = Metrics::new().unwrap();
+        let registry = prometheus::Registry::new();
+        metrics.register(&registry).unwrap();
+
+        metrics.jobs_activated("payment-service", 2);
+        let complete = JobResult::Complete { variables: None };
+        metrics.job_handled("payment-service", Some(&complete), Duration::from_millis(5));
+        metrics.job_handled("payment-service", None, Duration::from_millis(5));
+        metrics.grpc_error(&Error::DeadlineExceeded);
+
+        let count = |counter: &prometheus::IntCounterVec, labels: &[&str]| {
+            counter.with_label_values(labels).get()
+        };
+        assert_eq!(count(&metrics.jobs_activated, &["payment-service"]), 2);
+        assert_eq!(count(&metrics.jobs_completed, &["payment-service"]), 1);
+        assert_eq!(count(&metrics.jobs_failed, &["payment-service"]), 1);
+        assert_eq!(count(&metrics.grpc_errors, &["DeadlineExceeded", ""]), 1);
+    }
+}
@@ -0,0 +1,238 @@
+//! A scripted in-memory gateway for unit tests. Jobs queued with `MockGateway::with_jobs` are
+//! handed out on activation, and every completed and failed job is recorded.
+use crate::gateway;
+use crate::gateway_grpc::Gateway;
+use crate::Client;
+use futures::{FutureExt, TryFutureExt};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+pub struct MockGateway {
+    pub jobs: Arc<Mutex<Vec<gateway::ActivatedJob>>>,
+    pub activate_requests: Arc<Mutex<Vec<gateway::ActivateJobsRequest>>>,
+    pub completed: Arc<Mutex<Vec<gateway::CompleteJobRequest>>>,
+    pub failed: Arc<Mutex<Vec<gateway::FailJobRequest>>>,
+    pub authorizations: Arc<Mutex<Vec<Option<String>>>>,
+    pub metadata: Arc<Mutex<Vec<grpc::Metadata>>>,
+    pub cancelled: Arc<Mutex<Vec<gateway::CancelWorkflowInstanceRequest>>>,
+    pub variables: Arc<Mutex<Vec<gateway::SetVariablesRequest>>>,
+    pub retries: Arc<Mutex<Vec<gateway::UpdateJobRetriesRequest>>>,
+    pub resolved: Arc<Mutex<Vec<gateway::ResolveIncidentRequest>>>,
+    pub deployed: Arc<Mutex<Vec<gateway::DeployWorkflowRequest>>>,
+    pub created: Arc<Mutex<Vec<gateway::CreateWorkflowInstanceRequest>>>,
+    pub topology: Arc<Mutex<gateway::TopologyResponse>>,
+    /// The number of upcoming `complete_job` calls to reject because of backpressure.
+    pub backpressure: Arc<Mutex<usize>>,
+    pub published: Arc<Mutex<Vec<gateway::PublishMessageRequest>>>,
+    /// The number of upcoming `publish_message` calls to fail because the gateway is unavailable.
+    pub unavailable: Arc<Mutex<usize>>,
+    /// How long `create_workflow_instance` calls take; they resolve at once if this is not set.
+    pub create_delay: Arc<Mutex<Option<Duration>>>,
+    /// The `create_workflow_instance` calls in flight right now, and the most at once.
+    pub creates_in_flight: Arc<Mutex<(usize, usize)>>,
+}
+
+impl MockGateway {
+    /// Queue `count` jobs with keys `0..count`.
+    pub fn with_jobs(count: i64) -> Self {
+        let jobs = (0..count)
+            .map(|key| {
+                let mut job = gateway::ActivatedJob::default();
+                job.set_key(key);
+                job.set_retries(3);
+                job.set_variables("{}".to_string());
+                job.set_customHeaders("{}".to_string());
+                job
+            })
+            .collect();
+        Self {
+            jobs: Arc::new(Mutex::new(jobs)),
+            ..Default::default()
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        Client::from_gateway_client(Arc::new(self.clone()))
+    }
+
+    pub fn requested_amounts(&self) -> Vec<i32> {
+        self.activate_requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.maxJobsToActivate)
+            .collect()
+    }
+
+    pub fn completed_keys(&self) -> Vec<i64> {
+        let mut keys: Vec<i64> = self
+            .completed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.jobKey)
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl Gateway for MockGateway {
+    fn activate_jobs(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::ActivateJobsRequest,
+    ) -> grpc::StreamingResponse<gateway::ActivateJobsResponse> {
+        let amount = p.maxJobsToActivate as usize;
+        self.activate_requests.lock().unwrap().push(p);
+        let mut jobs = self.jobs.lock().unwrap();
+        let amount = amount.min(jobs.len());
+        let activated: Vec<gateway::ActivatedJob> = jobs.drain(..amount).collect();
+        let mut response = gateway::ActivateJobsResponse::default();
+        response.set_jobs(protobuf::RepeatedField::from(activated));
+        grpc::StreamingResponse::completed(vec![response])
+    }
+
+    fn cancel_workflow_instance(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::CancelWorkflowInstanceRequest,
+    ) -> grpc::SingleResponse<gateway::CancelWorkflowInstanceResponse> {
+        self.cancelled.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn complete_job(
+        &self,
+        o: grpc::RequestOptions,
+        p: gateway::CompleteJobRequest,
+    ) -> grpc::SingleResponse<gateway::CompleteJobResponse> {
+        let authorization = o
+            .metadata
+            .get("authorization")
+            .map(|value| String::from_utf8_lossy(value).into_owned());
+        self.authorizations.lock().unwrap().push(authorization);
+        self.metadata.lock().unwrap().push(o.metadata.clone());
+        let mut backpressure = self.backpressure.lock().unwrap();
+        if *backpressure > 0 {
+            *backpressure -= 1;
+            return grpc::SingleResponse::err(grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+                grpc_status: crate::backpressure::RESOURCE_EXHAUSTED,
+                grpc_message: "backpressure".to_string(),
+            }));
+        }
+        self.completed.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn create_workflow_instance(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::CreateWorkflowInstanceRequest,
+    ) -> grpc::SingleResponse<gateway::CreateWorkflowInstanceResponse> {
+        let mut created = self.created.lock().unwrap();
+        let mut response = gateway::CreateWorkflowInstanceResponse::default();
+        response.set_bpmnProcessId(p.bpmnProcessId.clone());
+        response.set_version(std::cmp::max(p.version, 1));
+        response.set_workflowKey(p.workflowKey);
+        response.set_workflowInstanceKey(created.len() as i64 + 1);
+        created.push(p);
+        let delay = *self.create_delay.lock().unwrap();
+        let delay = match delay {
+            Some(delay) => delay,
+            None => return grpc::SingleResponse::completed(response),
+        };
+        {
+            let mut in_flight = self.creates_in_flight.lock().unwrap();
+            in_flight.0 += 1;
+            in_flight.1 = std::cmp::max(in_flight.0, in_flight.1);
+        }
+        let in_flight = self.creates_in_flight.clone();
+        let call = futures_timer::Delay::new(delay).map(move |_| {
+            in_flight.lock().unwrap().0 -= 1;
+            Ok::<_, grpc::Error>(response)
+        });
+        grpc::SingleResponse::no_metadata(call.boxed().compat())
+    }
+
+    fn deploy_workflow(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::DeployWorkflowRequest,
+    ) -> grpc::SingleResponse<gateway::DeployWorkflowResponse> {
+        let mut deployed = self.deployed.lock().unwrap();
+        let mut response = gateway::DeployWorkflowResponse::default();
+        response.set_key(deployed.len() as i64 + 1);
+        for (key, resource) in p.workflows.iter().enumerate() {
+            let mut workflow = gateway::WorkflowMetadata::default();
+            workflow.set_resourceName(resource.name.clone());
+            workflow.set_version(1);
+            workflow.set_workflowKey(key as i64 + 1);
+            response.mut_workflows().push(workflow);
+        }
+        deployed.push(p);
+        grpc::SingleResponse::completed(response)
+    }
+
+    fn fail_job(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::FailJobRequest,
+    ) -> grpc::SingleResponse<gateway::FailJobResponse> {
+        self.failed.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn publish_message(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::PublishMessageRequest,
+    ) -> grpc::SingleResponse<gateway::PublishMessageResponse> {
+        self.published.lock().unwrap().push(p);
+        let mut unavailable = self.unavailable.lock().unwrap();
+        if *unavailable > 0 {
+            *unavailable -= 1;
+            return grpc::SingleResponse::err(grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+                grpc_status: 14,
+                grpc_message: "unavailable".to_string(),
+            }));
+        }
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn resolve_incident(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::ResolveIncidentRequest,
+    ) -> grpc::SingleResponse<gateway::ResolveIncidentResponse> {
+        self.resolved.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn set_variables(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::SetVariablesRequest,
+    ) -> grpc::SingleResponse<gateway::SetVariablesResponse> {
+        self.variables.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+
+    fn topology(
+        &self,
+        _o: grpc::RequestOptions,
+        _p: gateway::TopologyRequest,
+    ) -> grpc::SingleResponse<gateway::TopologyResponse> {
+        grpc::SingleResponse::completed(self.topology.lock().unwrap().clone())
+    }
+
+    fn update_job_retries(
+        &self,
+        _o: grpc::RequestOptions,
+        p: gateway::UpdateJobRetriesRequest,
+    ) -> grpc::SingleResponse<gateway::UpdateJobRetriesResponse> {
+        self.retries.lock().unwrap().push(p);
+        grpc::SingleResponse::completed(Default::default())
+    }
+}
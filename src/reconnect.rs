@@ -0,0 +1,248 @@
+use crate::gateway_grpc::Gateway;
+use crate::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How a `Client` re-establishes its connection to the gateway after a transport failure, e.g.
+/// when the gateway restarts.
+///
+/// After a call fails with a connection error the next call opens a new connection. While the
+/// gateway stays unreachable new connections are opened at most every `initial_delay`, doubling
+/// up to `max_delay`; the delay resets once a call succeeds. The failed call itself is not
+/// repeated, since the gateway may have processed it. Set it with
+/// `ClientBuilder::reconnect_policy`; by default the delay starts at 100 milliseconds and grows
+/// up to ten seconds.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The delay before the first reconnect.
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// The upper bound of the delay between reconnects.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    fn delay(&self, failures: u32) -> Duration {
+        let factor = 1u32 << failures.saturating_sub(1).min(16);
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| std::cmp::min(delay, self.max_delay))
+    }
+}
+
+type Connect = dyn Fn() -> Result<Arc<dyn Gateway + Send + Sync>, Error> + Send + Sync;
+
+/// The current connection of a client, replaced by a new one after a transport failure. Every
+/// connection has a generation so that a failure reported for an old connection does not tear
+/// down its replacement. Without a policy the connection is never replaced.
+pub(crate) struct Connection {
+    connect: Box<Connect>,
+    policy: Option<ReconnectPolicy>,
+    state: Mutex<ConnectionState>,
+}
+
+struct ConnectionState {
+    gateway_client: Arc<dyn Gateway + Send + Sync>,
+    generation: u64,
+    failures: u32,
+    reconnect_at: Option<Instant>,
+}
+
+impl Connection {
+    pub fn new<F>(
+        gateway_client: Arc<dyn Gateway + Send + Sync>,
+        policy: Option<ReconnectPolicy>,
+        connect: F,
+    ) -> Self
+    where
+        F: Fn() -> Result<Arc<dyn Gateway + Send + Sync>, Error> + Send + Sync + 'static,
+    {
+        Self {
+            connect: Box::new(connect),
+            policy,
+            state: Mutex::new(ConnectionState {
+                gateway_client,
+                generation: 0,
+                failures: 0,
+                reconnect_at: None,
+            }),
+        }
+    }
+
+    /// The gateway client to use for the next call and its generation. Opens a new connection if
+    /// the current one failed and the reconnect delay has passed.
+    pub fn gateway_client(&self) -> (u64, Arc<dyn Gateway + Send + Sync>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reconnect_at) = state.reconnect_at {
+            if Instant::now() >= reconnect_at {
+                state.reconnect_at = None;
+                // if the new connection cannot even be set up, keep the old one and try again later
+                match (self.connect)() {
+                    Ok(gateway_client) => {
+                        state.gateway_client = gateway_client;
+                        state.generation += 1;
+                    }
+                    Err(_) => self.schedule_reconnect(&mut state),
+                }
+            }
+        }
+        (state.generation, state.gateway_client.clone())
+    }
+
+    /// Report the outcome of a call made on the connection of `generation`.
+    pub fn report(&self, generation: u64, result: Result<(), &Error>) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        match result {
+            Ok(()) => state.failures = 0,
+            Err(e)
+                if e.is_connection_error()
+                    && self.policy.is_some()
+                    && state.reconnect_at.is_none() =>
+            {
+                self.schedule_reconnect(&mut state)
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn schedule_reconnect(&self, state: &mut ConnectionState) {
+        let delay = match &self.policy {
+            Some(policy) if state.failures > 0 => policy.delay(state.failures),
+            _ => Duration::from_secs(0),
+        };
+        state.failures += 1;
+        state.reconnect_at = Some(Instant::now() + delay);
+    }
+}
+
+/// The index of a connection in a `ConnectionPool` and its generation.
+pub(crate) type Generation = (usize, u64);
+
+/// The connections of a client. Calls take turns on them, so that the calls of a very busy client
+/// are spread over several HTTP/2 connections. Each connection is replaced on its own after a
+/// transport failure.
+pub(crate) struct ConnectionPool {
+    connections: Vec<Connection>,
+    next: AtomicUsize,
+}
+
+impl ConnectionPool {
+    /// A pool of `connections`, which must not be empty.
+    pub fn new(connections: Vec<Connection>) -> Self {
+        assert!(
+            !connections.is_empty(),
+            "a connection pool needs a connection"
+        );
+        Self {
+            connections,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The gateway client to use for the next call and the generation of its connection.
+    pub fn gateway_client(&self) -> (Generation, Arc<dyn Gateway + Send + Sync>) {
+        let index = self.next.fetch_add(1, Ordering::SeqCst) % self.connections.len();
+        let (generation, gateway_client) = self.connections[index].gateway_client();
+        ((index, generation), gateway_client)
+    }
+
+    /// Report the outcome of a call made on the connection of `generation`.
+    pub fn report(&self, (index, generation): Generation, result: Result<(), &Error>) {
+        self.connections[index].report(generation, result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock_gateway::MockGateway;
+    use crate::reconnect::{Connection, ConnectionPool};
+    use crate::{Error, ReconnectPolicy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn connection_error() -> Error {
+        Error::CompleteJobError(grpc::Error::Io(std::io::ErrorKind::ConnectionReset.into()))
+    }
+
+    fn connection(policy: Option<ReconnectPolicy>) -> (Connection, Arc<AtomicUsize>) {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let counted = connects.clone();
+        let connection = Connection::new(Arc::new(MockGateway::default()), policy, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(MockGateway::default()))
+        });
+        (connection, connects)
+    }
+
+    #[test]
+    fn reconnects_after_a_connection_error() {
+        let (connection, connects) = connection(Some(ReconnectPolicy::new()));
+        let (generation, _) = connection.gateway_client();
+        connection.report(generation, Err(&Error::DeadlineExceeded));
+        assert_eq!(connection.gateway_client().0, generation);
+
+        connection.report(generation, Err(&connection_error()));
+        let (reconnected, _) = connection.gateway_client();
+        assert_eq!(reconnected, generation + 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        // a late failure of the old connection leaves the new one alone
+        connection.report(generation, Err(&connection_error()));
+        assert_eq!(connection.gateway_client().0, reconnected);
+    }
+
+    #[test]
+    fn waits_before_reconnecting_again() {
+        let policy = ReconnectPolicy::new().initial_delay(Duration::from_secs(3600));
+        let (connection, connects) = connection(Some(policy));
+        connection.report(0, Err(&connection_error()));
+        assert_eq!(connection.gateway_client().0, 1);
+
+        connection.report(1, Err(&connection_error()));
+        assert_eq!(connection.gateway_client().0, 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn takes_turns_on_the_connections_of_a_pool() {
+        let pool = ConnectionPool::new(vec![
+            connection(Some(ReconnectPolicy::new())).0,
+            connection(None).0,
+        ]);
+        let generations: Vec<_> = (0..3).map(|_| pool.gateway_client().0).collect();
+        assert_eq!(generations, vec![(0, 0), (1, 0), (0, 0)]);
+
+        // only the failed connection is replaced, and only if it has a reconnect policy
+        pool.report((0, 0), Err(&connection_error()));
+        pool.report((1, 0), Err(&connection_error()));
+        let generations: Vec<_> = (0..2).map(|_| pool.gateway_client().0).collect();
+        assert_eq!(generations, vec![(1, 0), (0, 1)]);
+    }
+}
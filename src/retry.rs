@@ -0,0 +1,154 @@
+use crate::backpressure::jittered_delay;
+use crate::{Error, ErrorKind};
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a `Client` retries idempotent commands that failed for a transient reason, e.g. while the
+/// gateway restarts. The policy applies to `topology`, to deployments, and to `publish_message`
+/// for messages with a message id, since zeebe rejects a second message with the same id while
+/// the first is buffered. Other commands are never retried, because the gateway may have
+/// processed them before the failure.
+///
+/// A call is attempted at most `max_attempts` times. The delay before retry `n` is drawn at
+/// random between half and all of `initial_delay * 2^(n - 1)`, capped at `max_delay`. By default
+/// calls failing with `ErrorKind::Connection` or `ErrorKind::Unavailable` are attempted up to
+/// three times, starting at 100 milliseconds. Set it with `ClientBuilder::retry_policy`; without
+/// one idempotent commands fail right away, apart from the retries of `BackpressureRetry`, which
+/// happen within every attempt.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    retryable: Vec<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            retryable: vec![ErrorKind::Connection, ErrorKind::Unavailable],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often a call is attempted in total before it fails.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// The delay before the first retry.
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// The upper bound of the delay between retries.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// The kinds of errors to retry on, replacing the defaults. `ErrorKind` sorts gRPC status
+    /// codes, e.g. `UNAVAILABLE` is `ErrorKind::Unavailable` and `DEADLINE_EXCEEDED` is
+    /// `ErrorKind::DeadlineExceeded`.
+    pub fn retry_on<I: IntoIterator<Item = ErrorKind>>(mut self, retryable: I) -> Self {
+        self.retryable = retryable.into_iter().collect();
+        self
+    }
+
+    fn is_retryable(&self, error: &Error) -> bool {
+        self.retryable.contains(&error.kind())
+    }
+
+    /// Run `call` and attempt it again while it fails with a retryable error.
+    pub(crate) fn retry<T, F>(
+        self,
+        call: F,
+    ) -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>
+    where
+        T: Send + 'static,
+        F: Fn() -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>> + Send + Sync + 'static,
+    {
+        attempt(Arc::new(call), self, 1)
+    }
+}
+
+fn attempt<T, F>(
+    call: Arc<F>,
+    retry_policy: RetryPolicy,
+    attempt_number: u32,
+) -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>
+where
+    T: Send + 'static,
+    F: Fn() -> Pin<Box<dyn Future<Output = Result<T, Error>> + Send>> + Send + Sync + 'static,
+{
+    (*call)()
+        .then(move |result| match result {
+            Err(ref e)
+                if attempt_number < retry_policy.max_attempts && retry_policy.is_retryable(e) =>
+            {
+                let delay = jittered_delay(
+                    retry_policy.initial_delay,
+                    retry_policy.max_delay,
+                    attempt_number,
+                );
+                futures_timer::Delay::new(delay)
+                    .then(move |_| attempt(call, retry_policy, attempt_number + 1))
+                    .left_future()
+            }
+            result => futures::future::ready(result).right_future(),
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, ErrorKind, RetryPolicy};
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn unavailable() -> Error {
+        Error::TopologyError(grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+            grpc_status: 14,
+            grpc_message: "unavailable".to_string(),
+        }))
+    }
+
+    #[test]
+    fn retries_retryable_errors_up_to_max_attempts() {
+        let retry_policy = RetryPolicy::new()
+            .max_attempts(3)
+            .initial_delay(Duration::from_millis(1));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let result = futures::executor::block_on(retry_policy.clone().retry(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            futures::future::err::<(), _>(unavailable()).boxed()
+        }));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Unavailable);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let retry_policy = retry_policy.retry_on(vec![ErrorKind::Connection]);
+        let result = futures::executor::block_on(retry_policy.retry(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            futures::future::err::<(), _>(unavailable()).boxed()
+        }));
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
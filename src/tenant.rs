@@ -0,0 +1,155 @@
+use crate::{Client, Error, JobWorkerBuilder, ZeebeGateway};
+use std::collections::BTreeMap;
+
+/// Routes calls to the gateways of several zeebe clusters, one per tenant. Register a client for
+/// every tenant, optionally pick a default tenant, and look up the client of a tenant for every
+/// call; `None` selects the default tenant.
+///
+/// ```rust,ignore
+/// let router = TenantRouter::new()
+///     .tenant("acme", Client::new("acme-zeebe", 26500)?)
+///     .tenant("globex", Client::new("globex-zeebe", 26500)?)
+///     .default_tenant("acme");
+///
+/// router.client(Some("globex"))?.publish(message);
+/// let worker = router.worker(Some("globex"), "payment-service")?.build(handler);
+/// ```
+///
+/// The clients are independent, so every tenant has its own connection, credentials and
+/// settings. Workers built with `worker` poll the cluster of their tenant; run one worker per
+/// tenant that has jobs of the type.
+#[derive(Clone)]
+pub struct TenantRouter<G = Client> {
+    clients: BTreeMap<String, G>,
+    default_tenant: Option<String>,
+}
+
+impl<G> Default for TenantRouter<G> {
+    fn default() -> Self {
+        Self {
+            clients: BTreeMap::new(),
+            default_tenant: None,
+        }
+    }
+}
+
+impl<G> TenantRouter<G>
+where
+    G: ZeebeGateway + Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route the calls of `tenant` to `client`, replacing the client registered before.
+    pub fn tenant<S: Into<String>>(mut self, tenant: S, client: G) -> Self {
+        self.clients.insert(tenant.into(), client);
+        self
+    }
+
+    /// The tenant that is selected with `None`. Without a default tenant `None` selects nothing.
+    /// The default tenant must be registered with `tenant` as well.
+    pub fn default_tenant<S: Into<String>>(mut self, tenant: S) -> Self {
+        self.default_tenant = Some(tenant.into());
+        self
+    }
+
+    /// The registered tenants, in alphabetical order.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.clients.keys().map(String::as_str)
+    }
+
+    /// The client of `tenant`, or of the default tenant for `None`. Fails with
+    /// `Error::UnknownTenant` if the tenant is not registered, and with
+    /// `Error::ConfigurationError` for `None` if there is no default tenant or the default
+    /// tenant is not registered.
+    pub fn client(&self, tenant: Option<&str>) -> Result<G, Error> {
+        match (tenant, &self.default_tenant) {
+            (Some(tenant), _) => self
+                .clients
+                .get(tenant)
+                .cloned()
+                .ok_or_else(|| Error::UnknownTenant(tenant.to_string())),
+            (None, Some(default_tenant)) => {
+                self.clients.get(default_tenant).cloned().ok_or_else(|| {
+                    Error::ConfigurationError(format!(
+                        "the default tenant {} is not registered",
+                        default_tenant
+                    ))
+                })
+            }
+            (None, None) => Err(Error::ConfigurationError(
+                "no tenant selected and no default tenant".to_string(),
+            )),
+        }
+    }
+
+    /// Start configuring a worker for `job_type` that polls the cluster of `tenant`, see
+    /// `JobWorker::builder`.
+    pub fn worker<S: Into<String>>(
+        &self,
+        tenant: Option<&str>,
+        job_type: S,
+    ) -> Result<JobWorkerBuilder, Error> {
+        self.client(tenant)
+            .map(|client| JobWorkerBuilder::new(client, job_type))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::MockClient;
+    use crate::{Error, JobResult, TenantRouter, ZeebeGateway};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn routes_calls_and_workers_to_the_selected_tenant() {
+        let acme = MockClient::new();
+        let globex = MockClient::new();
+        let variables: BTreeMap<String, i32> = BTreeMap::new();
+        globex.add_job(1, "payment-service", &variables).unwrap();
+        let router = TenantRouter::new()
+            .tenant("acme", acme.clone())
+            .tenant("globex", globex.clone())
+            .default_tenant("acme");
+        assert_eq!(router.tenants().collect::<Vec<_>>(), vec!["acme", "globex"]);
+
+        let worker = router
+            .worker(Some("globex"), "payment-service")
+            .unwrap()
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert_eq!(globex.completed()[0].job_key, 1);
+
+        let client = router.client(None).unwrap();
+        futures::executor::block_on(client.cancel_workflow_instance(7)).unwrap();
+        assert_eq!(acme.cancelled(), vec![7]);
+        assert!(globex.cancelled().is_empty());
+
+        match router.client(Some("initech")) {
+            Err(Error::UnknownTenant(tenant)) => assert_eq!(tenant, "initech"),
+            _ => panic!("expected an unknown tenant"),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_unregistered_default_tenants() {
+        let router = TenantRouter::new().tenant("acme", MockClient::new());
+        match router.client(None) {
+            Err(Error::ConfigurationError(message)) => {
+                assert_eq!(message, "no tenant selected and no default tenant")
+            }
+            _ => panic!("expected a missing default tenant"),
+        }
+
+        let router = router.default_tenant("globex");
+        match router.client(None) {
+            Err(Error::ConfigurationError(message)) => {
+                assert_eq!(message, "the default tenant globex is not registered")
+            }
+            _ => panic!("expected an unregistered default tenant"),
+        }
+        assert!(router.worker(None, "payment-service").is_err());
+    }
+}
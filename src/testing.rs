@@ -0,0 +1,349 @@
+//! Test doubles for code that talks to zeebe. `MockClient` implements `ZeebeGateway`, so job
+//! handlers, `JobWorker`s and `WorkerGroup`s can be tested without a running broker:
+//!
+//! ```rust,ignore
+//! let client = MockClient::new();
+//! client.add_job(1, "payment-service", &json!({"total": 25.95})).unwrap();
+//! let worker = JobWorker::builder(client.clone(), "payment-service").build(handler);
+//! block_on(worker.activate_and_process_jobs());
+//! assert_eq!(client.completed()[0].job_key, 1);
+//! ```
+use crate::gateway;
+use crate::{
+    serialize_variables, ActivateJobs, ActivatedJob, ActivatedJobs, CompleteJob,
+    CreatedWorkflowInstance, DeployRequest, DeployedWorkflows, Error, PublishMessage, Topology,
+    WorkflowInstance, ZeebeGateway,
+};
+use futures::{Future, FutureExt, Stream, StreamExt};
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// A job that was failed through the mock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedJob {
+    pub job_key: i64,
+    pub retries: i32,
+    pub error_message: String,
+}
+
+/// A message that was published through the mock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublishedMessage {
+    pub name: String,
+    pub correlation_key: String,
+    pub time_to_live: i64,
+    pub message_id: String,
+    pub variables: String,
+}
+
+/// A workflow instance that was created through the mock. `workflow_key` is zero for instances
+/// created by BPMN process id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreatedInstance {
+    pub bpmn_process_id: String,
+    pub version: i32,
+    pub workflow_key: i64,
+    pub variables: String,
+}
+
+#[derive(Default)]
+struct MockState {
+    jobs: Vec<ActivatedJob>,
+    activations: Vec<ActivateJobs>,
+    completed: Vec<CompleteJob>,
+    failed: Vec<FailedJob>,
+    published: Vec<PublishedMessage>,
+    created: Vec<CreatedInstance>,
+    deployed: Vec<DeployRequest>,
+    cancelled: Vec<i64>,
+    variables: Vec<(i64, String, bool)>,
+    retries: Vec<(i64, i32)>,
+    resolved: Vec<i64>,
+}
+
+/// An in-memory `ZeebeGateway` that hands out scripted jobs and records every request. Clones
+/// share their state, so keep a clone to inspect what a worker did with the other one.
+///
+/// Jobs added with `add_job` or `push_job` are handed out by `activate_jobs` to activations of a
+/// matching job type, at most `max_jobs_to_activate` at a time. All other calls succeed.
+#[derive(Clone, Default)]
+pub struct MockClient {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a job of `job_type` with `variables`, which must serialize to a JSON object.
+    pub fn add_job<S: Into<String>, V: Serialize>(
+        &self,
+        key: i64,
+        job_type: S,
+        variables: &V,
+    ) -> Result<(), Error> {
+        self.push_job(ActivatedJob {
+            key,
+            field_type: job_type.into(),
+            custom_headers: "{}".to_string(),
+            worker: String::new(),
+            retries: 3,
+            deadline: 0,
+            variables: serialize_variables(variables)?,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    /// Queue a job to be handed out by `activate_jobs`.
+    pub fn push_job(&self, job: ActivatedJob) {
+        self.state.lock().unwrap().jobs.push(job);
+    }
+
+    /// The jobs that were not activated yet.
+    pub fn pending_jobs(&self) -> Vec<ActivatedJob> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    pub fn activations(&self) -> Vec<ActivateJobs> {
+        self.state.lock().unwrap().activations.clone()
+    }
+
+    pub fn completed(&self) -> Vec<CompleteJob> {
+        self.state.lock().unwrap().completed.clone()
+    }
+
+    pub fn failed(&self) -> Vec<FailedJob> {
+        self.state.lock().unwrap().failed.clone()
+    }
+
+    pub fn published(&self) -> Vec<PublishedMessage> {
+        self.state.lock().unwrap().published.clone()
+    }
+
+    pub fn created_instances(&self) -> Vec<CreatedInstance> {
+        self.state.lock().unwrap().created.clone()
+    }
+
+    /// Every deployment, with the names and definitions of its resources.
+    pub fn deployed(&self) -> Vec<DeployRequest> {
+        self.state.lock().unwrap().deployed.clone()
+    }
+
+    pub fn cancelled(&self) -> Vec<i64> {
+        self.state.lock().unwrap().cancelled.clone()
+    }
+
+    /// The element instance key, variables and `local` flag of every `set_variables` call.
+    pub fn variables(&self) -> Vec<(i64, String, bool)> {
+        self.state.lock().unwrap().variables.clone()
+    }
+
+    /// The job key and retries of every `update_job_retries` call.
+    pub fn updated_retries(&self) -> Vec<(i64, i32)> {
+        self.state.lock().unwrap().retries.clone()
+    }
+
+    pub fn resolved_incidents(&self) -> Vec<i64> {
+        self.state.lock().unwrap().resolved.clone()
+    }
+
+    fn record<F: FnOnce(&mut MockState)>(
+        &self,
+        record: F,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        record(&mut self.state.lock().unwrap());
+        futures::future::ok(()).boxed()
+    }
+}
+
+impl ZeebeGateway for MockClient {
+    fn topology(&self) -> Pin<Box<dyn Future<Output = Result<Topology, Error>> + Send>> {
+        futures::future::ok(Topology::new(Default::default())).boxed()
+    }
+
+    fn deploy_bpmn_workflow(
+        &self,
+        workflow_name: String,
+        workflow_definition: Vec<u8>,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        self.deploy(DeployRequest::new().bpmn(workflow_name, workflow_definition))
+    }
+
+    fn deploy(
+        &self,
+        deploy_request: DeployRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<DeployedWorkflows, Error>> + Send>> {
+        let mut state = self.state.lock().unwrap();
+        let mut response = gateway::DeployWorkflowResponse::default();
+        response.set_key(state.deployed.len() as i64 + 1);
+        for resource in deploy_request.resources() {
+            let mut workflow = gateway::WorkflowMetadata::default();
+            workflow.set_resourceName(resource.name.clone());
+            workflow.set_version(1);
+            response.mut_workflows().push(workflow);
+        }
+        state.deployed.push(deploy_request);
+        futures::future::ok(DeployedWorkflows::new(response)).boxed()
+    }
+
+    fn create_workflow_instance(
+        &self,
+        workflow_instance: WorkflowInstance,
+    ) -> Pin<Box<dyn Future<Output = Result<CreatedWorkflowInstance, Error>> + Send>> {
+        let request: gateway::CreateWorkflowInstanceRequest = workflow_instance.into();
+        let mut state = self.state.lock().unwrap();
+        state.created.push(CreatedInstance {
+            bpmn_process_id: request.bpmnProcessId.clone(),
+            version: request.version,
+            workflow_key: request.workflowKey,
+            variables: request.variables,
+        });
+        let mut response = gateway::CreateWorkflowInstanceResponse::default();
+        response.set_bpmnProcessId(request.bpmnProcessId);
+        response.set_version(request.version);
+        response.set_workflowKey(request.workflowKey);
+        response.set_workflowInstanceKey(state.created.len() as i64);
+        futures::future::ok(CreatedWorkflowInstance::new(response)).boxed()
+    }
+
+    fn activate_jobs(
+        &self,
+        jobs_config: ActivateJobs,
+    ) -> Pin<Box<dyn Stream<Item = Result<ActivatedJobs, Error>> + Send>> {
+        let mut state = self.state.lock().unwrap();
+        let mut activated_jobs = Vec::new();
+        let mut pending = Vec::new();
+        for job in state.jobs.drain(..) {
+            let wanted = (activated_jobs.len() as i32) < jobs_config.max_jobs_to_activate;
+            if wanted && job.field_type == jobs_config.job_type {
+                activated_jobs.push(ActivatedJob {
+                    worker: jobs_config.worker.clone(),
+                    ..job
+                });
+            } else {
+                pending.push(job);
+            }
+        }
+        state.jobs = pending;
+        state.activations.push(jobs_config);
+        futures::stream::iter(vec![Ok(ActivatedJobs { activated_jobs })]).boxed()
+    }
+
+    fn complete_job(
+        &self,
+        complete_job: CompleteJob,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| state.completed.push(complete_job))
+    }
+
+    fn fail_job(
+        &self,
+        job_key: i64,
+        retries: i32,
+        error_message: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| {
+            state.failed.push(FailedJob {
+                job_key,
+                retries,
+                error_message,
+            })
+        })
+    }
+
+    fn publish_message(
+        &self,
+        publish_message: PublishMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let request: gateway::PublishMessageRequest = publish_message.into();
+        self.record(|state| {
+            state.published.push(PublishedMessage {
+                name: request.name,
+                correlation_key: request.correlationKey,
+                time_to_live: request.timeToLive,
+                message_id: request.messageId,
+                variables: request.variables,
+            })
+        })
+    }
+
+    fn cancel_workflow_instance(
+        &self,
+        workflow_instance_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| state.cancelled.push(workflow_instance_key))
+    }
+
+    fn set_variables_json(
+        &self,
+        element_instance_key: i64,
+        variables: String,
+        local: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| {
+            state
+                .variables
+                .push((element_instance_key, variables, local))
+        })
+    }
+
+    fn update_job_retries(
+        &self,
+        job_key: i64,
+        retries: i32,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| state.retries.push((job_key, retries)))
+    }
+
+    fn resolve_incident(
+        &self,
+        incident_key: i64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        self.record(|state| state.resolved.push(incident_key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::{FailedJob, MockClient};
+    use crate::{JobResult, JobWorker};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn runs_a_worker_against_scripted_jobs() {
+        let client = MockClient::new();
+        let mut variables = BTreeMap::new();
+        variables.insert("total", 10);
+        client.add_job(1, "payment-service", &variables).unwrap();
+        client.add_job(2, "payment-service", &variables).unwrap();
+        client.add_job(3, "shipping-service", &variables).unwrap();
+
+        let worker = JobWorker::builder(client.clone(), "payment-service")
+            .worker_name("rusty-worker")
+            .build(|job| {
+                let result = if job.key == 1 {
+                    JobResult::Complete { variables: None }
+                } else {
+                    JobResult::fail("declined")
+                };
+                futures::future::ready(result).boxed()
+            });
+        futures::executor::block_on(worker.activate_and_process_jobs());
+
+        assert_eq!(client.activations()[0].worker, "rusty-worker");
+        assert_eq!(client.completed()[0].job_key, 1);
+        assert_eq!(
+            client.failed(),
+            vec![FailedJob {
+                job_key: 2,
+                retries: 2,
+                error_message: "declined".to_string(),
+            }]
+        );
+        assert_eq!(client.pending_jobs()[0].key, 3);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::gateway_grpc::GatewayClient;
+use crate::{Client, Error};
+use grpc::ClientStub;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use tls_api::{TlsConnector, TlsConnectorBuilder};
+
+/// Describes how to secure the connection to the gateway. The default configuration verifies the
+/// gateway against the system root certificates, which is enough for Camunda Cloud.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    root_certificates: Vec<Vec<u8>>,
+    client_identity: Option<(Vec<u8>, String)>,
+    domain: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust an additional PEM encoded root certificate, e.g. the CA of a self-signed gateway.
+    pub fn root_certificate_pem(mut self, pem: Vec<u8>) -> Self {
+        self.root_certificates.push(pem);
+        self
+    }
+
+    /// Authenticate the client with a PKCS #12 archive containing the client certificate and key.
+    pub fn client_identity_pkcs12<S: Into<String>>(mut self, der: Vec<u8>, password: S) -> Self {
+        self.client_identity = Some((der, password.into()));
+        self
+    }
+
+    /// The domain used for SNI and to verify the gateway certificate. Defaults to the host the
+    /// client connects to.
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub(crate) fn gateway_client(
+        &self,
+        host: &str,
+        port: u16,
+        conf: grpc::ClientConf,
+    ) -> Result<GatewayClient, Error> {
+        let connector = self.connector().map_err(|e| Error::TlsError(e))?;
+        let domain = self.domain.clone().unwrap_or_else(|| host.to_string());
+        let addr = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| Error::GatewayError(grpc::Error::Io(e)))?
+            .next()
+            .ok_or_else(|| {
+                Error::GatewayError(grpc::Error::Other("could not resolve gateway address"))
+            })?;
+        grpc::Client::new_expl(
+            &addr,
+            host,
+            httpbis::ClientTlsOption::Tls(domain, Arc::new(connector)),
+            conf,
+        )
+        .map_err(|e| Error::GatewayError(e))
+        .map(|grpc_client| GatewayClient::with_client(Arc::new(grpc_client)))
+    }
+
+    fn connector(&self) -> Result<tls_api_native_tls::TlsConnector, tls_api::Error> {
+        let mut builder = tls_api_native_tls::TlsConnector::builder()?;
+        {
+            let native_builder = builder.underlying_mut();
+            for pem in &self.root_certificates {
+                let certificate =
+                    native_tls::Certificate::from_pem(pem).map_err(tls_api::Error::new)?;
+                native_builder.add_root_certificate(certificate);
+            }
+            if let Some((der, password)) = &self.client_identity {
+                let identity = native_tls::Identity::from_pkcs12(der, password)
+                    .map_err(tls_api::Error::new)?;
+                native_builder.identity(identity);
+            }
+        }
+        if tls_api_native_tls::TlsConnector::supports_alpn() {
+            builder.set_alpn_protocols(&[b"h2"])?;
+        }
+        builder.build()
+    }
+}
+
+impl Client {
+    /// Construct a new `Client` that connects to a broker with `host` and `port` over TLS.
+    pub fn new_tls(host: &str, port: u16, tls_config: TlsConfig) -> Result<Self, Error> {
+        Client::builder(host, port).tls(tls_config).build()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{Error, JobResult};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// Accumulates the output variables of a job while its handler runs. Handlers created with
+/// `JobWorker::new_with_context` receive a context and may call `set` from any step of their
+/// future. When the handler resolves, the worker builds the `CompleteJob` payload from it:
+///
+/// - `JobResult::Complete { variables: None }` completes the job with the accumulated variables
+/// (or with no variables if nothing was set).
+/// - `JobResult::Complete { variables: Some(..) }` completes the job with the explicit variables;
+/// the context is ignored.
+/// - `JobResult::Fail` and `JobResult::NoAction` discard the context.
+#[derive(Clone, Debug, Default)]
+pub struct CompletionContext {
+    variables: Arc<Mutex<serde_json::Map<String, serde_json::Value>>>,
+}
+
+impl CompletionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set an output variable. Setting a variable twice overwrites the earlier value.
+    pub fn set<K: Into<String>, S: Serialize>(&self, name: K, value: &S) -> Result<(), Error> {
+        let value = serde_json::to_value(value).map_err(|e| Error::JsonError(e))?;
+        self.variables.lock().unwrap().insert(name.into(), value);
+        Ok(())
+    }
+
+    /// Remove a previously set output variable.
+    pub fn remove(&self, name: &str) {
+        self.variables.lock().unwrap().remove(name);
+    }
+
+    /// Returns true if no output variables were set.
+    pub fn is_empty(&self) -> bool {
+        self.variables.lock().unwrap().is_empty()
+    }
+
+    /// Merge the accumulated variables into the result returned by the handler.
+    pub fn finish(self, job_result: JobResult) -> JobResult {
+        match job_result {
+            JobResult::Complete { variables: None } => {
+                let variables = self.variables.lock().unwrap();
+                if variables.is_empty() {
+                    JobResult::Complete { variables: None }
+                } else {
+                    JobResult::Complete {
+                        variables: Some(serde_json::Value::Object(variables.clone()).to_string()),
+                    }
+                }
+            }
+            job_result => job_result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompletionContext, JobResult};
+    use futures::FutureExt;
+
+    #[test]
+    fn accumulates_variables_across_steps() {
+        let context = CompletionContext::new();
+        let ctx = context.clone();
+        let job_result = futures::executor::block_on(
+            futures::future::ready(10)
+                .map(move |order_id| {
+                    ctx.set("orderId", &order_id).unwrap();
+                    ctx
+                })
+                .map(|ctx| {
+                    ctx.set("shipped", &true).unwrap();
+                    JobResult::Complete { variables: None }
+                }),
+        );
+        let job_result = context.finish(job_result);
+        assert_eq!(
+            job_result,
+            JobResult::Complete {
+                variables: Some(r#"{"orderId":10,"shipped":true}"#.to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn conditional_variables() {
+        for &insured in &[true, false] {
+            let context = CompletionContext::new();
+            context.set("orderId", &10).unwrap();
+            if insured {
+                context.set("insurance", &"premium").unwrap();
+            }
+            let job_result = context.finish(JobResult::Complete { variables: None });
+            let expected = if insured {
+                r#"{"insurance":"premium","orderId":10}"#
+            } else {
+                r#"{"orderId":10}"#
+            };
+            assert_eq!(
+                job_result,
+                JobResult::Complete {
+                    variables: Some(expected.to_string())
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn empty_context_completes_without_variables() {
+        let context = CompletionContext::new();
+        let job_result = context.finish(JobResult::Complete { variables: None });
+        assert_eq!(job_result, JobResult::Complete { variables: None });
+    }
+
+    #[test]
+    fn explicit_result_takes_precedence() {
+        let context = CompletionContext::new();
+        context.set("orderId", &10).unwrap();
+        let explicit = JobResult::Complete {
+            variables: Some(r#"{"orderId":11}"#.to_string()),
+        };
+        assert_eq!(context.clone().finish(explicit.clone()), explicit);
+        let fail = JobResult::Fail {
+            error_message: None,
+            retries: None,
+        };
+        assert_eq!(context.finish(fail.clone()), fail);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{ActivatedJob, Client, CompleteJob, JobResult};
+use crate::{ActivatedJob, CompleteJob, JobResult, ZeebeGateway};
 use futures::{Future, FutureExt};
 use std::pin::Pin;
 use std::sync::Arc;
@@ -9,6 +9,7 @@ pub trait JobStatusReporter {
         key: i64,
         variables: Option<String>,
     ) -> Pin<Box<dyn Future<Output = Result<(), crate::Error>> + Send>>;
+    /// Fail the job, leaving it with `retries` retries.
     fn fail(
         &self,
         key: i64,
@@ -18,12 +19,14 @@ pub trait JobStatusReporter {
 }
 
 pub struct Reporter {
-    client: Client,
+    client: Arc<dyn ZeebeGateway + Send + Sync>,
 }
 
 impl Reporter {
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new<G: ZeebeGateway + Send + Sync + 'static>(client: G) -> Self {
+        Self {
+            client: Arc::new(client),
+        }
     }
 }
 
@@ -37,7 +40,7 @@ impl JobStatusReporter for Reporter {
             job_key: key,
             variables,
         };
-        self.client.complete_job(complete_job).boxed()
+        self.client.complete_job(complete_job)
     }
 
     fn fail(
@@ -47,9 +50,7 @@ impl JobStatusReporter for Reporter {
         error_message: Option<String>,
     ) -> Pin<Box<dyn Future<Output = Result<(), crate::Error>> + Send>> {
         let error_message = error_message.unwrap_or("".to_string());
-        self.client
-            .fail_job(key, retries - 1, error_message)
-            .boxed()
+        self.client.fail_job(key, retries, error_message)
     }
 }
 
@@ -73,10 +74,13 @@ impl JobClient {
         let retries = activated_job.retries;
         match job_result {
             JobResult::NoAction => futures::future::ok(()).boxed(),
-            JobResult::Fail { error_message } => match error_message {
-                Some(msg) => self.complete.fail(key, retries, Some(msg)),
-                None => self.complete.fail(key, retries, None),
-            },
+            JobResult::Fail {
+                error_message,
+                retries: retries_left,
+            } => {
+                let retries_left = retries_left.unwrap_or(retries - 1);
+                self.complete.fail(key, retries_left, error_message)
+            }
             JobResult::Complete { variables } => self.complete.complete(key, variables),
         }
     }
@@ -94,6 +98,7 @@ mod test {
     struct MockReporter {
         completed: Arc<RwLock<Option<i64>>>,
         fail: Arc<RwLock<Option<i64>>>,
+        fail_retries: Arc<RwLock<Option<i32>>>,
     }
 
     impl MockReporter {
@@ -101,6 +106,7 @@ mod test {
             Self {
                 completed: Arc::new(RwLock::new(None)),
                 fail: Arc::new(RwLock::new(None)),
+                fail_retries: Arc::new(RwLock::new(None)),
             }
         }
     }
@@ -119,11 +125,12 @@ mod test {
         fn fail(
             &self,
             key: i64,
-            _retries: i32,
+            retries: i32,
             _error_message: Option<String>,
         ) -> Pin<Box<dyn Future<Output = Result<(), crate::Error>> + Send>> {
             let mut write = self.fail.write().unwrap();
             *write = Some(key);
+            *self.fail_retries.write().unwrap() = Some(retries);
             futures::future::ok(()).boxed()
         }
     }
@@ -142,6 +149,7 @@ mod test {
             retries: 0,
             deadline: 0,
             variables: "".to_string(),
+            ..Default::default()
         };
         let result = JobResult::Complete { variables: None };
         let _ =
@@ -166,9 +174,11 @@ mod test {
             retries: 0,
             deadline: 0,
             variables: "".to_string(),
+            ..Default::default()
         };
         let result = JobResult::Fail {
             error_message: None,
+            retries: None,
         };
         let _ =
             futures::executor::block_on(job_client.report_status(activated_job, result)).unwrap();
@@ -177,4 +187,32 @@ mod test {
         assert_eq!(completed, None);
         assert_eq!(failed, Some(100));
     }
+
+    #[test]
+    fn decrements_retries_unless_explicit() {
+        let completer = MockReporter::new();
+        let job_client = JobClient {
+            complete: Arc::new(completer.clone()),
+        };
+        let activated_job = crate::ActivatedJob {
+            key: 100,
+            field_type: "".to_string(),
+            custom_headers: "".to_string(),
+            worker: "".to_string(),
+            retries: 3,
+            deadline: 0,
+            variables: "".to_string(),
+            ..Default::default()
+        };
+        let _ = futures::executor::block_on(
+            job_client.report_status(activated_job.clone(), JobResult::fail("oh no")),
+        )
+        .unwrap();
+        assert_eq!(*completer.fail_retries.read().unwrap(), Some(2));
+        let _ = futures::executor::block_on(
+            job_client.report_status(activated_job, JobResult::fail_with_retries("oh no", 0)),
+        )
+        .unwrap();
+        assert_eq!(*completer.fail_retries.read().unwrap(), Some(0));
+    }
 }
@@ -0,0 +1,256 @@
+use crate::worker::polling::StartDelay;
+use crate::{
+    saturating_millis, ActivatedJob, Handler, JobResult, JobWorker, PanicOption, WorkerHandle,
+    ZeebeGateway,
+};
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A single worker for several job types. Register a handler for each job type with `route` and
+/// the dispatcher activates jobs of all of them and hands every activated job to the handler of
+/// its type.
+///
+/// Unlike the workers of a `WorkerGroup`, the job types of a dispatcher share the client, the
+/// worker settings, the poll interval, the idle backoff and the start delay. The gateway activates
+/// one job type per request, so `run` polls every job type in a loop of its own, and a job type
+/// with slow jobs or without jobs never holds back the others. Each job type keeps its own
+/// `max_jobs` slots.
+///
+/// ```rust,ignore
+/// let (handle, run) = JobDispatcher::new(client)
+///     .worker_name("rusty-worker")
+///     .route("initiate-payment", payment_handler)
+///     .route("ship-with-insurance", shipping_handler)
+///     .run(Duration::from_secs(1));
+/// ```
+pub struct JobDispatcher {
+    client: Arc<dyn ZeebeGateway + Send + Sync>,
+    worker_name: String,
+    timeout: Duration,
+    max_jobs: u16,
+    panic_option: PanicOption,
+    max_idle_backoff: Option<Duration>,
+    start_delay: StartDelay,
+    workers: Vec<JobWorker>,
+}
+
+impl JobDispatcher {
+    pub fn new<G: ZeebeGateway + Send + Sync + 'static>(client: G) -> Self {
+        Self {
+            client: Arc::new(client),
+            worker_name: "zeebest".to_string(),
+            timeout: Duration::from_secs(300),
+            max_jobs: 32,
+            panic_option: PanicOption::FailJobOnPanic,
+            max_idle_backoff: None,
+            start_delay: StartDelay::default(),
+            workers: Vec::new(),
+        }
+    }
+
+    /// The worker name used for job types routed after this call. Defaults to `zeebest`.
+    pub fn worker_name<S: Into<String>>(mut self, worker_name: S) -> Self {
+        self.worker_name = worker_name.into();
+        self
+    }
+
+    /// How long activated jobs are locked, for job types routed after this call. Defaults to five
+    /// minutes.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many jobs of each job type routed after this call are processed at once. Defaults to
+    /// 32.
+    pub fn max_jobs(mut self, max_jobs: u16) -> Self {
+        self.max_jobs = max_jobs;
+        self
+    }
+
+    /// What to do when a handler routed after this call panics. Defaults to failing the job.
+    pub fn panic_option(mut self, panic_option: PanicOption) -> Self {
+        self.panic_option = panic_option;
+        self
+    }
+
+    /// Cap the idle backoff of `run`, see `JobWorkerBuilder::max_idle_backoff`.
+    pub fn max_idle_backoff(mut self, max_idle_backoff: Duration) -> Self {
+        self.max_idle_backoff = Some(max_idle_backoff);
+        self
+    }
+
+    /// Wait for `poll_offset` before the first poll of `run`, see
+    /// `JobWorkerBuilder::poll_offset`.
+    pub fn poll_offset(mut self, poll_offset: Duration) -> Self {
+        self.start_delay.offset = poll_offset;
+        self
+    }
+
+    /// Wait for a random delay of up to `start_jitter` before the first poll of `run`, see
+    /// `JobWorkerBuilder::start_jitter`.
+    pub fn start_jitter(mut self, start_jitter: Duration) -> Self {
+        self.start_delay.jitter = start_jitter;
+        self
+    }
+
+    /// Process the jobs of `job_type` with `job_handler`.
+    pub fn route<S, F>(self, job_type: S, job_handler: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let worker = JobWorker::new(
+            self.worker_name.clone(),
+            job_type.into(),
+            saturating_millis(self.timeout),
+            self.max_jobs,
+            self.panic_option,
+            self.client.clone(),
+            job_handler,
+        );
+        self.worker(worker)
+    }
+
+    /// Process the jobs of `job_type` with a `Handler`.
+    pub fn route_handler<S, H>(self, job_type: S, handler: H) -> Self
+    where
+        S: Into<String>,
+        H: Handler + 'static,
+    {
+        self.route(job_type, move |activated_job| {
+            handler.handle(activated_job).boxed()
+        })
+    }
+
+    /// Route the jobs of a worker that was configured separately, e.g. with `JobWorker::builder`.
+    /// The worker is polled with the poll interval, idle backoff and start delay of the
+    /// dispatcher; it keeps its own client.
+    pub fn worker(mut self, worker: JobWorker) -> Self {
+        self.workers.push(worker);
+        self
+    }
+
+    /// Activate one round of jobs of every job type and process them. Resolves to the number of
+    /// activated jobs once all of them are processed.
+    pub fn activate_and_process_jobs(&self) -> Pin<Box<dyn Future<Output = usize> + Send>> {
+        let rounds: Vec<_> = self
+            .workers
+            .iter()
+            .map(|worker| worker.job_internal.clone().activate_and_process_jobs())
+            .collect();
+        futures::future::join_all(rounds)
+            .map(|activated| activated.into_iter().sum())
+            .boxed()
+    }
+
+    /// Run a managed polling loop for every job type, like `JobWorker::run`, that all stop
+    /// through the returned `WorkerHandle`. The idle backoff of every job type starts at
+    /// `poll_interval`; all of them wait for the same start delay before their first poll.
+    pub fn run(
+        self,
+        poll_interval: Duration,
+    ) -> (WorkerHandle, Pin<Box<dyn Future<Output = ()> + Send>>) {
+        let worker_handle = WorkerHandle::new();
+        let drained = worker_handle.drained.clone();
+        let idle_backoff = super::idle_backoff(poll_interval, self.max_idle_backoff);
+        let start_delay = self.start_delay.next_delay();
+        let loops: Vec<_> = self
+            .workers
+            .into_iter()
+            .map(|worker| {
+                let idle_backoff = idle_backoff.clone();
+                worker
+                    .job_internal
+                    .poll(idle_backoff, start_delay, &worker_handle)
+            })
+            .collect();
+        let run = futures::future::join_all(loops)
+            .map(move |_| drained.raise())
+            .boxed();
+        (worker_handle, run)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::MockClient;
+    use crate::worker::test::{blocked_job, complete, wait_until};
+    use crate::{JobDispatcher, JobResult};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn routes_jobs_to_the_handler_of_their_type() {
+        let client = MockClient::new();
+        let variables: BTreeMap<String, i32> = BTreeMap::new();
+        client.add_job(1, "payment-service", &variables).unwrap();
+        client.add_job(2, "shipping-service", &variables).unwrap();
+        client.add_job(3, "payment-service", &variables).unwrap();
+
+        let dispatcher = JobDispatcher::new(client.clone())
+            .worker_name("rusty-worker")
+            .route("payment-service", |_| {
+                futures::future::ready(JobResult::Complete { variables: None }).boxed()
+            })
+            .route("shipping-service", |_| {
+                futures::future::ready(JobResult::fail("no truck")).boxed()
+            });
+        let activated = futures::executor::block_on(dispatcher.activate_and_process_jobs());
+
+        assert_eq!(activated, 3);
+        let mut completed: Vec<i64> = client.completed().iter().map(|c| c.job_key).collect();
+        completed.sort();
+        assert_eq!(completed, vec![1, 3]);
+        assert_eq!(client.failed()[0].job_key, 2);
+        let activations = client.activations();
+        assert_eq!(activations.len(), 2);
+        assert!(activations.iter().all(|a| a.worker == "rusty-worker"));
+    }
+
+    #[test]
+    fn caps_the_timeout_of_routed_job_types() {
+        let client = MockClient::new();
+        let dispatcher = JobDispatcher::new(client.clone())
+            .timeout(Duration::new(u64::max_value(), 0))
+            .route("payment-service", |_| {
+                futures::future::ready(JobResult::Complete { variables: None }).boxed()
+            });
+        futures::executor::block_on(dispatcher.activate_and_process_jobs());
+        assert_eq!(client.activations()[0].timeout, i64::max_value());
+    }
+
+    #[test]
+    fn a_slow_job_type_does_not_hold_back_the_others() {
+        let client = MockClient::new();
+        let variables: BTreeMap<String, i32> = BTreeMap::new();
+        client.add_job(1, "payment-service", &variables).unwrap();
+        client.add_job(2, "shipping-service", &variables).unwrap();
+        client.add_job(3, "shipping-service", &variables).unwrap();
+        let (release, released) = futures::channel::oneshot::channel::<()>();
+        let released = released.shared();
+
+        let (worker_handle, run) = JobDispatcher::new(client.clone())
+            .max_jobs(1)
+            .route("payment-service", move |_| blocked_job(&released))
+            .route("shipping-service", complete)
+            .run(Duration::from_millis(1));
+        let observer = client.clone();
+        let stop = wait_until(move || observer.completed().len() == 2).then(move |_| {
+            // both shipping jobs went through one at a time while the payment job was running
+            release.send(()).unwrap();
+            worker_handle.shutdown()
+        });
+        futures::executor::block_on(futures::future::join(run, stop));
+        let mut completed: Vec<i64> = client.completed().iter().map(|c| c.job_key).collect();
+        assert_eq!(completed[..2], [2, 3]);
+        completed.sort();
+        assert_eq!(completed, vec![1, 2, 3]);
+    }
+}
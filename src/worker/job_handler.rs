@@ -1,9 +1,54 @@
 use futures::{Future, FutureExt};
+use std::any::Any;
 use std::panic;
 use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
 
+/// Processes activated jobs, as an alternative to a closure for handlers that keep state in
+/// struct fields, e.g. a connection pool or an HTTP client. `handle` returns a future of the
+/// handler's own type, so handlers need not box it. Closures taking an `ActivatedJob` and
+/// returning a future implement this trait, so code can be generic over both forms.
+///
+/// ```rust,ignore
+/// struct Payments {
+///     http_client: Arc<HttpClient>,
+/// }
+///
+/// impl Handler for Payments {
+///     type Future = futures::future::Map<Charge, fn(Receipt) -> JobResult>;
+///
+///     fn handle(&self, job: ActivatedJob) -> Self::Future {
+///         charge(self.http_client.clone(), job).map(|_| JobResult::Complete { variables: None })
+///     }
+/// }
+///
+/// let worker = JobWorker::builder(client, "payment-service").build_handler(Payments { http_client });
+/// ```
+///
+/// The returned future must not borrow the handler, since it may outlive the call; clone what it
+/// needs out of `self`. The trait has no `async fn` (or `#[async_trait]`) because this crate is
+/// built on futures 0.3 combinators without async/await; the `Future` type plays the part of the
+/// future an `async fn` would return.
+pub trait Handler: Send + Sync {
+    /// The future that processes a job.
+    type Future: Future<Output = crate::JobResult> + Send + 'static;
+
+    fn handle(&self, activated_job: crate::ActivatedJob) -> Self::Future;
+}
+
+impl<F, R> Handler for F
+where
+    F: Fn(crate::ActivatedJob) -> R + Send + Sync,
+    R: Future<Output = crate::JobResult> + Send + 'static,
+{
+    type Future = R;
+
+    fn handle(&self, activated_job: crate::ActivatedJob) -> R {
+        self(activated_job)
+    }
+}
+
 pub struct JobHandler {
     job_handler: Arc<
         dyn Fn(crate::ActivatedJob) -> Pin<Box<dyn Future<Output = crate::JobResult> + Send>>
@@ -13,18 +58,19 @@ pub struct JobHandler {
 }
 
 impl JobHandler {
+    /// Run the handler on `activated_job`. Fails with the panic message if the handler panics.
     pub fn process_job(
         &self,
         activated_job: crate::ActivatedJob,
-    ) -> Pin<Box<dyn Future<Output = Result<crate::JobResult, ()>> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Result<crate::JobResult, String>> + Send>> {
         let job_handler = self.job_handler.clone();
         let result = panic::catch_unwind(AssertUnwindSafe(|| (job_handler)(activated_job)));
         match result {
-            Err(_) => futures::future::err(()).boxed(),
+            Err(payload) => futures::future::err(panic_message(payload)).boxed(),
             Ok(f) => AssertUnwindSafe(f)
                 .catch_unwind()
                 .then(|r| match r {
-                    Err(_) => futures::future::err(()),
+                    Err(payload) => futures::future::err(panic_message(payload)),
                     Ok(jr) => futures::future::ok(jr),
                 })
                 .boxed(),
@@ -42,6 +88,22 @@ impl JobHandler {
     }
 }
 
+/// Run `f`, failing with the panic message if it panics.
+pub(crate) fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(panic_message)
+}
+
+/// The message a panic was raised with, if it was raised with a string like `panic!` does.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "worker panicked".to_string(),
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::JobResult;
@@ -62,9 +124,10 @@ mod tests {
             retries: 0,
             deadline: 0,
             variables: "".to_string(),
+            ..Default::default()
         };
         let result = futures::executor::block_on(jh.process_job(aj));
-        assert!(result.is_err(), "Job panicked but did not error");
+        assert_eq!(result, Err("oh no!".to_string()));
     }
 
     #[test]
@@ -80,6 +143,7 @@ mod tests {
             retries: 0,
             deadline: 0,
             variables: "".to_string(),
+            ..Default::default()
         };
         let result = futures::executor::block_on(jh.process_job(aj));
         assert!(result.is_ok(), "Job errored with non-panicking handler");
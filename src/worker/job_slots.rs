@@ -0,0 +1,164 @@
+use crate::worker::JobInternal;
+use futures::task::{Context, Poll};
+use futures::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Capacity claimed from a worker for a single activation. The slots are claimed atomically, so
+/// concurrent activations (e.g. from clones of the same `JobWorker`) can never request more jobs
+/// than the worker is allowed to run. Slots that the gateway does not fill are given back when
+/// `release_remaining` is called or when the reservation is dropped.
+pub struct JobReservation {
+    job_internal: Arc<JobInternal>,
+    remaining: AtomicUsize,
+    claimed: AtomicUsize,
+}
+
+impl JobReservation {
+    pub fn new(job_internal: Arc<JobInternal>) -> Self {
+        let job_count = &job_internal.job_count;
+        let mut current = job_count.load(Ordering::SeqCst);
+        let amount = loop {
+            let available = job_internal.max_concurrent_jobs.saturating_sub(current);
+            match job_count.compare_exchange(
+                current,
+                current + available,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => break available,
+                Err(actual) => current = actual,
+            }
+        };
+        Self {
+            job_internal,
+            remaining: AtomicUsize::new(amount),
+            claimed: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of slots that are still reserved and not claimed by a job.
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// The number of jobs that claimed a slot.
+    pub fn claimed(&self) -> usize {
+        self.claimed.load(Ordering::SeqCst)
+    }
+
+    /// Claim a reserved slot for an activated job. The slot is freed when the returned `JobSlot`
+    /// is dropped.
+    pub fn claim(&self) -> JobSlot {
+        self.claimed.fetch_add(1, Ordering::SeqCst);
+        let mut remaining = self.remaining.load(Ordering::SeqCst);
+        loop {
+            if remaining == 0 {
+                // the gateway returned more jobs than requested, count them anyway
+                self.job_internal.job_count.fetch_add(1, Ordering::SeqCst);
+                break;
+            }
+            match self.remaining.compare_exchange(
+                remaining,
+                remaining - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => break,
+                Err(actual) => remaining = actual,
+            }
+        }
+        JobSlot {
+            job_internal: self.job_internal.clone(),
+        }
+    }
+
+    /// Give back `amount` of the slots that were not claimed.
+    pub fn release(&self, amount: usize) {
+        let mut remaining = self.remaining.load(Ordering::SeqCst);
+        let released = loop {
+            let released = std::cmp::min(amount, remaining);
+            match self.remaining.compare_exchange(
+                remaining,
+                remaining - released,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => break released,
+                Err(actual) => remaining = actual,
+            }
+        };
+        free_slots(&self.job_internal, released);
+    }
+
+    /// Give back all slots that were not claimed.
+    pub fn release_remaining(&self) {
+        let remaining = self.remaining.swap(0, Ordering::SeqCst);
+        free_slots(&self.job_internal, remaining);
+    }
+}
+
+impl Drop for JobReservation {
+    fn drop(&mut self) {
+        self.release_remaining();
+    }
+}
+
+/// A slot held by a single job while its handler runs.
+pub struct JobSlot {
+    job_internal: Arc<JobInternal>,
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        free_slots(&self.job_internal, 1);
+    }
+}
+
+/// Give `amount` slots back to the worker and wake the polling loop if it waits for a free slot.
+fn free_slots(job_internal: &JobInternal, amount: usize) {
+    if amount == 0 {
+        return;
+    }
+    job_internal.job_count.fetch_sub(amount, Ordering::SeqCst);
+    for waker in job_internal.slot_wakers.lock().unwrap().drain(..) {
+        waker.wake();
+    }
+}
+
+/// Resolves once the worker has a free slot.
+pub struct FreeSlot {
+    job_internal: Arc<JobInternal>,
+}
+
+impl FreeSlot {
+    pub fn new(job_internal: Arc<JobInternal>) -> Self {
+        Self { job_internal }
+    }
+
+    fn is_free(&self) -> bool {
+        let job_count = self.job_internal.job_count.load(Ordering::SeqCst);
+        job_count < self.job_internal.max_concurrent_jobs
+    }
+}
+
+impl Future for FreeSlot {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_free() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.job_internal.slot_wakers.lock().unwrap();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        // a slot may have been freed before the waker was registered
+        if self.is_free() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::worker::WorkerSettings;
+use crate::{
+    saturating_millis, ActivatedJob, CompletionContext, Handler, JobMiddleware, JobResult,
+    JobWorker, PanicOption, ZeebeGateway,
+};
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures a `JobWorker`. Create one with `JobWorker::builder` and finish it with `build`,
+/// with `build_with_context` for handlers that take a `CompletionContext`, or with
+/// `build_handler` for a `Handler`.
+///
+/// ```rust,ignore
+/// let worker = JobWorker::builder(client, "payment-service")
+///     .worker_name("rusty-worker")
+///     .timeout(Duration::from_secs(10))
+///     .max_jobs(2)
+///     .build(handler);
+/// ```
+#[derive(Clone)]
+pub struct JobWorkerBuilder {
+    client: Arc<dyn ZeebeGateway + Send + Sync>,
+    settings: WorkerSettings,
+}
+
+impl JobWorkerBuilder {
+    pub fn new<G, S>(client: G, job_type: S) -> Self
+    where
+        G: ZeebeGateway + Send + Sync + 'static,
+        S: Into<String>,
+    {
+        Self::with_client(Arc::new(client), job_type)
+    }
+
+    /// Configure a worker that shares `client` with other workers.
+    pub(crate) fn with_client<S: Into<String>>(
+        client: Arc<dyn ZeebeGateway + Send + Sync>,
+        job_type: S,
+    ) -> Self {
+        Self {
+            client,
+            settings: WorkerSettings::new(
+                "zeebest".to_string(),
+                job_type.into(),
+                saturating_millis(Duration::from_secs(5 * 60)),
+                32,
+                PanicOption::FailJobOnPanic,
+            ),
+        }
+    }
+
+    /// The name the worker activates jobs under. Defaults to `zeebest`.
+    pub fn worker_name<S: Into<String>>(mut self, worker_name: S) -> Self {
+        self.settings.worker_name = worker_name.into();
+        self
+    }
+
+    /// How long an activated job is locked to this worker. Defaults to five minutes.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.settings.timeout = saturating_millis(timeout);
+        self
+    }
+
+    /// The maximum number of jobs processed at once, i.e. of handlers running concurrently.
+    /// Activations never request more jobs than there are free slots. Defaults to 32.
+    pub fn max_jobs(mut self, max_jobs: u16) -> Self {
+        self.settings.max_jobs = max_jobs;
+        self
+    }
+
+    /// What to do when the handler panics. Defaults to failing the job.
+    pub fn panic_option(mut self, panic_option: PanicOption) -> Self {
+        self.settings.panic_option = panic_option;
+        self
+    }
+
+    /// Call `on_panic` with the job and the panic message whenever the handler panics, e.g. to
+    /// alert on it. The job is then handled according to the `PanicOption`.
+    pub fn on_panic<F>(mut self, on_panic: F) -> Self
+    where
+        F: Fn(&ActivatedJob, &str) + Send + Sync + 'static,
+    {
+        self.settings.on_panic = Some(Arc::new(on_panic));
+        self
+    }
+
+    /// Run `middleware` around the handler of every job, after the middleware that was added
+    /// before. See `JobMiddleware`.
+    pub fn middleware<M: JobMiddleware + 'static>(mut self, middleware: M) -> Self {
+        self.settings.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Process at most `max_jobs_per_second` jobs per second, e.g. to protect a fragile downstream
+    /// service. Activations only request as many jobs as the rate allows and wait for the rate
+    /// when it is exhausted, so jobs are never activated just to sit out their timeout. The rate is
+    /// shared by all clones of the worker, allows bursts of up to one second's worth of jobs, and
+    /// is at least one job per second.
+    pub fn max_jobs_per_second(mut self, max_jobs_per_second: u32) -> Self {
+        self.settings.max_jobs_per_second = Some(max_jobs_per_second);
+        self
+    }
+
+    /// Call `on_retries_exhausted` with the job and its error message whenever a job is failed
+    /// without retries left, once the failure is reported. Zeebe raises an incident for such a
+    /// job and does not hand it to a worker again, so this is the place to alert on it or to push
+    /// its payload onto a dead-letter queue. Jobs failed because their handler panicked count as
+    /// well, unless the `PanicOption` keeps their retries.
+    pub fn on_retries_exhausted<F>(mut self, on_retries_exhausted: F) -> Self
+    where
+        F: Fn(&ActivatedJob, &str) + Send + Sync + 'static,
+    {
+        self.settings.on_retries_exhausted = Some(Arc::new(on_retries_exhausted));
+        self
+    }
+
+    /// How long the gateway holds an activation open while it waits for jobs. By default the
+    /// gateway's long polling timeout is used. Polling the worker in a tight loop with a long
+    /// request timeout picks up new jobs as soon as they are created without wasting requests.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.settings.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Only fetch these variables with activated jobs, instead of every variable in scope of the
+    /// job. Workers that only read a few fields of a large payload should set this.
+    pub fn fetch_variables<I, S>(mut self, fetch_variables: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.settings.fetch_variables = fetch_variables.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Cap the idle backoff of `JobWorker::run` at `max_idle_backoff` instead of eight times the
+    /// poll interval. A high cap saves requests to an idle broker, at the cost of picking up new
+    /// work later.
+    pub fn max_idle_backoff(mut self, max_idle_backoff: Duration) -> Self {
+        self.settings.max_idle_backoff = Some(max_idle_backoff);
+        self
+    }
+
+    /// Wait for `poll_offset` before the first poll of `JobWorker::run`. Giving the workers of a
+    /// fleet different offsets spreads their activations over the poll interval.
+    pub fn poll_offset(mut self, poll_offset: Duration) -> Self {
+        self.settings.start_delay.offset = poll_offset;
+        self
+    }
+
+    /// Wait for a random delay of up to `start_jitter` before the first poll of `JobWorker::run`,
+    /// on top of the `poll_offset`, so that many worker processes started at the same time do not
+    /// poll in lockstep.
+    pub fn start_jitter(mut self, start_jitter: Duration) -> Self {
+        self.settings.start_delay.jitter = start_jitter;
+        self
+    }
+
+    /// Build a worker that processes jobs with `job_handler`.
+    pub fn build<F>(self, job_handler: F) -> JobWorker
+    where
+        F: Fn(ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        JobWorker::with_settings(self.client, self.settings, job_handler)
+    }
+
+    /// Build a worker that processes jobs with a `Handler`.
+    pub fn build_handler<H: Handler + 'static>(self, handler: H) -> JobWorker {
+        self.build(move |activated_job| handler.handle(activated_job).boxed())
+    }
+
+    /// Build a worker whose handler also receives a `CompletionContext`, see
+    /// `JobWorker::new_with_context`.
+    pub fn build_with_context<F>(self, job_handler: F) -> JobWorker
+    where
+        F: Fn(ActivatedJob, CompletionContext) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.build(super::with_context(job_handler))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock_gateway::MockGateway;
+    use crate::testing::MockClient;
+    use crate::{ActivatedJob, Handler, JobResult, JobWorker};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    struct Receipts {
+        currency: String,
+    }
+
+    impl Handler for Receipts {
+        type Future = futures::future::Ready<JobResult>;
+
+        fn handle(&self, job: ActivatedJob) -> Self::Future {
+            let mut receipt = BTreeMap::new();
+            receipt.insert("receipt", format!("{}-{}", self.currency, job.key));
+            futures::future::ready(JobResult::complete_with(&receipt).unwrap())
+        }
+    }
+
+    #[test]
+    fn builds_workers_from_handlers() {
+        let client = MockClient::new();
+        client
+            .add_job(7, "payment-service", &BTreeMap::<String, i32>::new())
+            .unwrap();
+        let worker =
+            JobWorker::builder(client.clone(), "payment-service").build_handler(Receipts {
+                currency: "EUR".to_string(),
+            });
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert_eq!(
+            client.completed()[0].variables.as_ref().unwrap(),
+            r#"{"receipt":"EUR-7"}"#
+        );
+
+        // closures implement `Handler` without boxing their future
+        client
+            .add_job(8, "payment-service", &BTreeMap::<String, i32>::new())
+            .unwrap();
+        let worker = JobWorker::builder(client.clone(), "payment-service")
+            .build_handler(|_: ActivatedJob| futures::future::ready(JobResult::fail("no funds")));
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert_eq!(client.failed()[0].job_key, 8);
+    }
+
+    #[test]
+    fn activates_with_the_configured_settings() {
+        let gateway = MockGateway::with_jobs(0);
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .timeout(Duration::from_secs(10))
+            .max_jobs(4)
+            .request_timeout(Duration::from_secs(2))
+            .fetch_variables(vec!["orderId", "total"])
+            .build(|_| futures::future::ready(JobResult::NoAction).boxed());
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let requests = gateway.activate_requests.lock().unwrap();
+        assert_eq!(requests[0].worker, "zeebest");
+        assert_eq!(requests[0].field_type, "payment-service");
+        assert_eq!(requests[0].timeout, 10_000);
+        assert_eq!(requests[0].maxJobsToActivate, 4);
+        assert_eq!(requests[0].requestTimeout, 2_000);
+        assert_eq!(&requests[0].fetchVariable[..], &["orderId", "total"]);
+
+        let builder = JobWorker::builder(gateway.client(), "payment-service")
+            .timeout(Duration::new(u64::max_value(), 0));
+        assert_eq!(builder.settings.timeout, i64::max_value());
+    }
+}
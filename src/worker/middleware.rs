@@ -0,0 +1,295 @@
+use crate::{ActivatedJob, JobResult};
+use std::sync::Arc;
+
+/// Hooks that run around the handler of every job of a worker, for cross-cutting concerns like
+/// logging, idempotency checks or enriching variables. Register them with
+/// `JobWorkerBuilder::middleware`; every hook has a default that does nothing, so implement only the
+/// ones you need. An `Arc` of a middleware is a middleware too, so one instance can be shared by
+/// several workers.
+///
+/// `before_job` hooks run in the order the middleware was added, `after_completion` and
+/// `on_failure` hooks in reverse order, so the first middleware wraps all others. The hooks run
+/// before the result is reported, and the metrics of the worker see the result the hooks leave
+/// behind. A middleware that short-circuits a job in `before_job` only has its own hooks and
+/// those of the middleware before it run on the result.
+///
+/// A hook that panics is handled like a panicking handler: the job is reported according to the
+/// `PanicOption`, and the `on_failure` hooks of the middleware that wraps the panicking one see
+/// the panic message.
+///
+/// ```rust,ignore
+/// struct SkipProcessed {
+///     processed: Arc<Mutex<HashSet<i64>>>,
+/// }
+///
+/// impl JobMiddleware for SkipProcessed {
+///     fn before_job(&self, job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+///         if self.processed.lock().unwrap().contains(&job.key) {
+///             Err(JobResult::Complete { variables: None })
+///         } else {
+///             Ok(job)
+///         }
+///     }
+/// }
+///
+/// let worker = JobWorker::builder(client, "payment-service")
+///     .middleware(SkipProcessed { processed })
+///     .build(handler);
+/// ```
+pub trait JobMiddleware: Send + Sync {
+    /// Called with every activated job before the handler runs. Return the job, possibly with
+    /// changed variables, to pass it on, or a `JobResult` to report it without running the
+    /// handler or the remaining `before_job` hooks.
+    fn before_job(&self, activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+        Ok(activated_job)
+    }
+
+    /// Called when a job is about to be completed, with the variables it completes with. The
+    /// returned variables are reported instead.
+    fn after_completion(
+        &self,
+        _activated_job: &ActivatedJob,
+        variables: Option<String>,
+    ) -> Option<String> {
+        variables
+    }
+
+    /// Called when a job is about to be failed, or when its handler panicked, with the error
+    /// message or the panic message.
+    fn on_failure(&self, _activated_job: &ActivatedJob, _error_message: &str) {}
+}
+
+impl<M: JobMiddleware + ?Sized> JobMiddleware for Arc<M> {
+    fn before_job(&self, activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+        (**self).before_job(activated_job)
+    }
+
+    fn after_completion(
+        &self,
+        activated_job: &ActivatedJob,
+        variables: Option<String>,
+    ) -> Option<String> {
+        (**self).after_completion(activated_job, variables)
+    }
+
+    fn on_failure(&self, activated_job: &ActivatedJob, error_message: &str) {
+        (**self).on_failure(activated_job, error_message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::MockClient;
+    use crate::{ActivatedJob, JobMiddleware, JobResult, JobWorker};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    struct Audit {
+        failures: Arc<Mutex<Vec<(i64, String)>>>,
+    }
+
+    impl JobMiddleware for Audit {
+        fn before_job(&self, activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+            if activated_job.key == 3 {
+                return Err(JobResult::Complete { variables: None });
+            }
+            Ok(ActivatedJob {
+                variables: r#"{"audited":true}"#.to_string(),
+                ..activated_job
+            })
+        }
+
+        fn after_completion(
+            &self,
+            activated_job: &ActivatedJob,
+            _variables: Option<String>,
+        ) -> Option<String> {
+            Some(format!(r#"{{"auditedJob":{}}}"#, activated_job.key))
+        }
+
+        fn on_failure(&self, activated_job: &ActivatedJob, error_message: &str) {
+            self.failures
+                .lock()
+                .unwrap()
+                .push((activated_job.key, error_message.to_string()));
+        }
+    }
+
+    #[test]
+    fn runs_hooks_around_the_handler() {
+        let client = MockClient::new();
+        for key in 1..4 {
+            client
+                .add_job(key, "payment-service", &BTreeMap::<String, i32>::new())
+                .unwrap();
+        }
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let handled_jobs = handled.clone();
+        let worker = JobWorker::builder(client.clone(), "payment-service")
+            .middleware(Audit {
+                failures: failures.clone(),
+            })
+            .build(move |job| {
+                handled_jobs
+                    .lock()
+                    .unwrap()
+                    .push((job.key, job.variables.clone()));
+                let job_result = if job.key == 1 {
+                    JobResult::Complete { variables: None }
+                } else {
+                    JobResult::fail("declined")
+                };
+                futures::future::ready(job_result).boxed()
+            });
+        futures::executor::block_on(worker.activate_and_process_jobs());
+
+        let mut handled = handled.lock().unwrap().clone();
+        handled.sort();
+        let audited = r#"{"audited":true}"#.to_string();
+        assert_eq!(handled, vec![(1, audited.clone()), (2, audited)]);
+        let mut completed: Vec<(i64, Option<String>)> = client
+            .completed()
+            .into_iter()
+            .map(|c| (c.job_key, c.variables))
+            .collect();
+        completed.sort();
+        assert_eq!(
+            completed,
+            vec![
+                (1, Some(r#"{"auditedJob":1}"#.to_string())),
+                (3, Some(r#"{"auditedJob":3}"#.to_string())),
+            ]
+        );
+        assert_eq!(*failures.lock().unwrap(), vec![(2, "declined".to_string())]);
+    }
+
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl JobMiddleware for Recorder {
+        fn before_job(&self, activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} before", self.name));
+            Ok(activated_job)
+        }
+
+        fn after_completion(
+            &self,
+            _activated_job: &ActivatedJob,
+            variables: Option<String>,
+        ) -> Option<String> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} after", self.name));
+            variables
+        }
+
+        fn on_failure(&self, _activated_job: &ActivatedJob, error_message: &str) {
+            let entry = format!("{} failed: {}", self.name, error_message);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    struct ShortCircuit;
+
+    impl JobMiddleware for ShortCircuit {
+        fn before_job(&self, _activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+            Err(JobResult::Complete { variables: None })
+        }
+    }
+
+    struct Panicking;
+
+    impl JobMiddleware for Panicking {
+        fn before_job(&self, activated_job: ActivatedJob) -> Result<ActivatedJob, JobResult> {
+            if activated_job.key == 1 {
+                panic!("before_job broke");
+            }
+            Ok(activated_job)
+        }
+
+        fn after_completion(
+            &self,
+            activated_job: &ActivatedJob,
+            variables: Option<String>,
+        ) -> Option<String> {
+            if activated_job.key == 2 {
+                panic!("after_completion broke");
+            }
+            variables
+        }
+    }
+
+    #[test]
+    fn short_circuits_only_unwind_the_middleware_that_saw_the_job() {
+        let client = MockClient::new();
+        client
+            .add_job(1, "payment-service", &BTreeMap::<String, i32>::new())
+            .unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let worker = JobWorker::builder(client.clone(), "payment-service")
+            .middleware(Recorder {
+                name: "outer",
+                log: log.clone(),
+            })
+            .middleware(ShortCircuit)
+            .middleware(Recorder {
+                name: "inner",
+                log: log.clone(),
+            })
+            .build(|_| panic!("the handler must not run"));
+        futures::executor::block_on(worker.activate_and_process_jobs());
+
+        assert_eq!(client.completed()[0].job_key, 1);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["outer before".to_string(), "outer after".to_string()]
+        );
+    }
+
+    #[test]
+    fn reports_panicking_hooks_like_panicking_handlers() {
+        let client = MockClient::new();
+        for key in 1..4 {
+            client
+                .add_job(key, "payment-service", &BTreeMap::<String, i32>::new())
+                .unwrap();
+        }
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let worker = JobWorker::builder(client.clone(), "payment-service")
+            .middleware(Recorder {
+                name: "outer",
+                log: log.clone(),
+            })
+            .middleware(Panicking)
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        let stats = worker.stats();
+        futures::executor::block_on(worker.activate_and_process_jobs());
+
+        assert_eq!(client.completed()[0].job_key, 3);
+        let mut failed: Vec<(i64, String)> = client
+            .failed()
+            .into_iter()
+            .map(|f| (f.job_key, f.error_message))
+            .collect();
+        failed.sort();
+        assert_eq!(
+            failed,
+            vec![
+                (1, "before_job broke".to_string()),
+                (2, "after_completion broke".to_string()),
+            ]
+        );
+        let log = log.lock().unwrap();
+        assert!(log.contains(&"outer failed: before_job broke".to_string()));
+        assert!(log.contains(&"outer failed: after_completion broke".to_string()));
+        assert_eq!(stats.jobs_in_flight(), 0);
+    }
+}
@@ -1,130 +1,585 @@
-use crate::{ActivateJobs, ActivatedJob, ActivatedJobs, Client};
+use crate::{
+    check_variables, saturating_millis, serialize_variables, ActivateJobs, ActivatedJob,
+    ActivatedJobs, Error, ZeebeGateway,
+};
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::task::Waker;
 use futures::{Future, FutureExt, StreamExt};
+use serde::Serialize;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+mod completion_context;
 mod job_client;
+mod job_dispatcher;
 mod job_handler;
+mod job_slots;
+mod job_worker_builder;
+mod middleware;
+mod polling;
+mod rate_limit;
+mod stats;
+mod worker_group;
 
+pub use completion_context::CompletionContext;
 pub use job_client::JobClient;
 pub use job_client::Reporter;
-pub use job_handler::JobHandler;
+pub use job_dispatcher::JobDispatcher;
+use job_handler::catch_panic;
+pub use job_handler::{Handler, JobHandler};
+use job_slots::{FreeSlot, JobReservation, JobSlot};
+pub use job_worker_builder::JobWorkerBuilder;
+pub use middleware::JobMiddleware;
+pub use polling::WorkerHandle;
+use polling::{IdleBackoff, Signal, StartDelay};
+use rate_limit::RateLimiter;
+use stats::Counters;
+pub use stats::WorkerStats;
+pub use worker_group::WorkerGroup;
 
 /// An option that describes what the job worker should do if if the job handler panics.
+///
+/// Jobs are failed with the panic message as the error message, so it shows up on the incident
+/// once the retries run out. To be alerted of panics regardless of the option, register a
+/// callback with `JobWorkerBuilder::on_panic`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PanicOption {
+    /// Fail the job and decrement its retries, like `JobResult::fail`.
     FailJobOnPanic,
+    /// Fail the job but keep its retries, so a panic never raises an incident on its own.
+    RetryJobOnPanic,
+    /// Leave the job alone; it is activated again once its timeout passes.
     DoNothingOnPanic,
 }
 
+/// A callback that is given a job and the message it failed with, see
+/// `JobWorkerBuilder::on_panic` and `JobWorkerBuilder::on_retries_exhausted`.
+type JobCallback = dyn Fn(&ActivatedJob, &str) + Send + Sync;
+
+/// The processing of an activated job: the middleware, the handler and reporting the result.
+type JobFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
 /// A result that describes the output of a job.
+///
+/// The variables of `Complete` must be a JSON object, as zeebe requires; a worker fails a job
+/// that is completed with anything else, with an error message that says so. `complete_with`
+/// checks the variables when the result is built.
+///
+/// `Fail` reports the job as failed with an optional error message. The job is retried with
+/// `retries` retries left; if `retries` is `None` the retries of the activated job are decremented
+/// by one. Once no retries are left zeebe raises an incident that shows the error message. The
+/// gateway protocol of this zeebe version has no retry backoff, so failed jobs can be activated
+/// again right away.
 #[derive(Clone, Debug, PartialEq)]
 pub enum JobResult {
-    Complete { variables: Option<String> },
-    Fail { error_message: Option<String> },
+    Complete {
+        variables: Option<String>,
+    },
+    Fail {
+        error_message: Option<String>,
+        retries: Option<i32>,
+    },
     NoAction,
 }
 
 impl JobResult {
+    /// Complete the job with `variables` serialized as JSON. Zeebe requires the variables to be a
+    /// JSON object, so anything else (e.g. an array or a number) is rejected.
+    pub fn complete_with<S: Serialize>(variables: &S) -> Result<Self, Error> {
+        serialize_variables(variables).map(|variables| JobResult::Complete {
+            variables: Some(variables),
+        })
+    }
+
+    /// Fail the job with an error message that is shown on the incident, once retries run out.
+    pub fn fail<S: Into<String>>(error_message: S) -> Self {
+        JobResult::Fail {
+            error_message: Some(error_message.into()),
+            retries: None,
+        }
+    }
+
+    /// Fail the job with an error message and an explicit number of retries left.
+    pub fn fail_with_retries<S: Into<String>>(error_message: S, retries: i32) -> Self {
+        JobResult::Fail {
+            error_message: Some(error_message.into()),
+            retries: Some(retries),
+        }
+    }
+
     pub fn into_result(self) -> Result<Option<String>, Option<String>> {
         match self {
             JobResult::Complete { variables } => Ok(variables),
-            JobResult::Fail { error_message } => Err(error_message),
+            JobResult::Fail { error_message, .. } => Err(error_message),
             JobResult::NoAction => Err(None),
         }
     }
 }
 
+/// The settings of a worker. `JobWorkerBuilder` collects them and the worker is created from them
+/// in one go, so a worker is never reconfigured once it is shared.
+#[derive(Clone)]
+pub(crate) struct WorkerSettings {
+    pub(crate) worker_name: String,
+    pub(crate) job_type: String,
+    pub(crate) timeout: i64,
+    pub(crate) max_jobs: u16,
+    pub(crate) panic_option: PanicOption,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) fetch_variables: Vec<String>,
+    pub(crate) max_idle_backoff: Option<Duration>,
+    pub(crate) start_delay: StartDelay,
+    pub(crate) on_panic: Option<Arc<JobCallback>>,
+    pub(crate) on_retries_exhausted: Option<Arc<JobCallback>>,
+    pub(crate) middleware: Vec<Arc<dyn JobMiddleware>>,
+    pub(crate) max_jobs_per_second: Option<u32>,
+}
+
+impl WorkerSettings {
+    pub(crate) fn new(
+        worker_name: String,
+        job_type: String,
+        timeout: i64,
+        max_jobs: u16,
+        panic_option: PanicOption,
+    ) -> Self {
+        Self {
+            worker_name,
+            job_type,
+            timeout,
+            max_jobs,
+            panic_option,
+            request_timeout: None,
+            fetch_variables: Vec::new(),
+            max_idle_backoff: None,
+            start_delay: StartDelay::default(),
+            on_panic: None,
+            on_retries_exhausted: None,
+            middleware: Vec::new(),
+            max_jobs_per_second: None,
+        }
+    }
+}
+
 pub struct JobInternal {
     job_handler: JobHandler,
-    job_count: AtomicUsize,
+    job_count: Arc<AtomicUsize>,
+    slot_wakers: Mutex<Vec<Waker>>,
     max_concurrent_jobs: usize,
-    client: Client,
+    client: Arc<dyn ZeebeGateway + Send + Sync>,
     job_client: JobClient,
     worker_name: String,
     job_type: String,
     timeout: i64,
+    request_timeout: i64,
+    fetch_variables: Vec<String>,
+    max_idle_backoff: Option<Duration>,
+    start_delay: StartDelay,
     panic_option: PanicOption,
+    on_panic: Option<Arc<JobCallback>>,
+    on_retries_exhausted: Option<Arc<JobCallback>>,
+    middleware: Vec<Arc<dyn JobMiddleware>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    counters: Arc<Counters>,
 }
 
 impl JobInternal {
-    pub fn activate_and_process_jobs(self: Arc<Self>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-        let current_job_count: usize = self.job_count.load(Ordering::SeqCst);
-        // assert on an unreachable edge case
-        assert!(
-            current_job_count <= self.max_concurrent_jobs,
-            "current number of jobs exceeds allowed number of running jobs"
+    /// Activate and process a batch of jobs. Resolves to the number of activated jobs once all of
+    /// them are processed.
+    pub fn activate_and_process_jobs(
+        self: Arc<Self>,
+    ) -> Pin<Box<dyn Future<Output = usize> + Send>> {
+        let (jobs, processing) = mpsc::unbounded();
+        // the activation owns the sender, so the processing ends once it is over and the jobs are
+        // done
+        let processing = processing.for_each_concurrent(None, |job| job);
+        futures::future::join(self.activate(jobs), processing)
+            .map(|(activated, _)| activated)
+            .boxed()
+    }
+
+    /// Run the managed polling loop of the worker until the handle is stopped or shut down. The
+    /// activated jobs are processed alongside the loop, which activates jobs again as soon as a
+    /// slot is free, so a slow job only holds on to its own slot.
+    fn poll(
+        self: Arc<Self>,
+        idle_backoff: IdleBackoff,
+        start_delay: Duration,
+        worker_handle: &WorkerHandle,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let (jobs, processing) = mpsc::unbounded();
+        let shutdown = worker_handle.shutdown.clone();
+        let polling = polling::poll_until(
+            move || {
+                self.clone()
+                    .activate_when_free(jobs.clone(), shutdown.clone())
+            },
+            idle_backoff,
+            start_delay,
+            worker_handle,
         );
+        // the loop owns the sender, so the processing ends once the loop is over and the jobs are
+        // done
+        let processing = processing.for_each_concurrent(None, |job| job).boxed();
+        futures::future::select(
+            futures::future::join(polling, processing),
+            worker_handle.stop.raised(),
+        )
+        .map(|_| ())
+        .boxed()
+    }
+
+    /// Wait for a free slot and activate jobs into the free slots. A shutdown cuts the wait short
+    /// and nothing is activated.
+    fn activate_when_free(
+        self: Arc<Self>,
+        jobs: UnboundedSender<JobFuture>,
+        shutdown: Signal,
+    ) -> Pin<Box<dyn Future<Output = usize> + Send>> {
+        futures::future::select(FreeSlot::new(self.clone()), shutdown.raised())
+            .then(move |_| {
+                if shutdown.is_raised() {
+                    futures::future::ready(0).boxed()
+                } else {
+                    self.activate(jobs)
+                }
+            })
+            .boxed()
+    }
+
+    /// Activate a batch of jobs into the free slots of the worker and hand the processing of every
+    /// activated job to `jobs`. Resolves to the number of activated jobs once the activation is
+    /// over, without waiting for the jobs to be processed.
+    fn activate(
+        self: Arc<Self>,
+        jobs: UnboundedSender<JobFuture>,
+    ) -> Pin<Box<dyn Future<Output = usize> + Send>> {
+        // claim the free capacity up front so concurrent activations cannot over-activate
+        let reservation = Arc::new(JobReservation::new(self.clone()));
+        let amount = reservation.remaining();
+        if amount == 0 {
+            return futures::future::ready(0).boxed();
+        }
+        match self.rate_limiter.clone() {
+            None => self.activate_jobs(reservation, amount, jobs),
+            // wait for permits and put the slots without one back
+            Some(rate_limiter) => rate_limiter
+                .acquire(amount)
+                .then(move |permits| {
+                    reservation.release(amount - permits);
+                    self.activate_jobs(reservation, permits, jobs)
+                })
+                .boxed(),
+        }
+    }
+
+    /// Activate up to `amount` jobs into the slots of `reservation` and hand them to `jobs`.
+    fn activate_jobs(
+        self: Arc<Self>,
+        reservation: Arc<JobReservation>,
+        amount: usize,
+        jobs: UnboundedSender<JobFuture>,
+    ) -> Pin<Box<dyn Future<Output = usize> + Send>> {
         let mut activate_jobs = ActivateJobs::new(
             self.worker_name.clone(),
             self.job_type.clone(),
             self.timeout,
-            0,
+            amount as _,
         );
-        activate_jobs.max_jobs_to_activate = (self.max_concurrent_jobs - current_job_count) as _;
+        activate_jobs.request_timeout = self.request_timeout;
+        activate_jobs.fetch_variables = self.fetch_variables.clone();
 
+        #[cfg(feature = "metrics")]
+        let activation_started = std::time::Instant::now();
         let activate_jobs_stream = self.client.activate_jobs(activate_jobs);
 
         let slf = self.clone();
+        let activated = reservation.clone();
+        let failed = AtomicBool::new(false);
         activate_jobs_stream
-            .for_each_concurrent(None, move |result| match result {
-                Err(_e) => futures::future::ready(()).boxed(),
-                Ok(ActivatedJobs { activated_jobs }) => {
-                    let slf = slf.clone();
-                    let job_count = activated_jobs.len();
-                    slf.job_count.fetch_add(job_count, Ordering::SeqCst);
-                    futures::stream::iter(activated_jobs)
-                        .for_each_concurrent(None, move |aj| {
-                            let slf = slf.clone();
-                            slf.job_handler
-                                .process_job(aj.clone())
-                                .then(move |result| {
-                                    slf.job_count.fetch_sub(1, Ordering::SeqCst);
-                                    match result {
-                                        Err(_) => match slf.panic_option {
-                                            PanicOption::FailJobOnPanic => {
-                                                slf.job_client.report_status(
-                                                    aj,
-                                                    JobResult::Fail {
-                                                        error_message: Some(
-                                                            "worker panicked".to_string(),
-                                                        ),
-                                                    },
-                                                )
-                                            }
-                                            PanicOption::DoNothingOnPanic => {
-                                                futures::future::ok(()).boxed()
-                                            }
-                                        },
-                                        Ok(job_result) => {
-                                            slf.job_client.report_status(aj, job_result)
-                                        }
-                                    }
-                                })
-                                .then(|_| futures::future::ready(()))
-                        })
-                        .boxed()
+            .map(Some)
+            .chain(futures::stream::once(futures::future::ready(None)))
+            .for_each(move |result| {
+                match result {
+                    // the activation is over, give back whatever the gateway did not fill
+                    None => {
+                        if let Some(rate_limiter) = &slf.rate_limiter {
+                            rate_limiter.give_back(reservation.remaining());
+                        }
+                        reservation.release_remaining();
+                        slf.counters.activation_finished(&failed);
+                        #[cfg(feature = "metrics")]
+                        slf.record(|metrics, job_type| {
+                            metrics.activation_finished(job_type, activation_started.elapsed())
+                        });
+                    }
+                    Some(Err(_e)) => failed.store(true, Ordering::SeqCst),
+                    Some(Ok(ActivatedJobs { activated_jobs })) => {
+                        slf.counters.jobs_activated(activated_jobs.len());
+                        #[cfg(feature = "metrics")]
+                        slf.record(|metrics, job_type| {
+                            metrics.jobs_activated(job_type, activated_jobs.len())
+                        });
+                        for aj in activated_jobs {
+                            let job = slf.clone().handle(reservation.claim(), aj);
+                            // once the worker is stopped nobody processes the job, and dropping
+                            // it frees its slot
+                            let _ = jobs.unbounded_send(job);
+                        }
+                    }
+                }
+                futures::future::ready(())
+            })
+            .map(move |_| activated.claimed())
+            .boxed()
+    }
+
+    /// Process an activated job that holds `slot` and report its result.
+    fn handle(self: Arc<Self>, slot: JobSlot, aj: ActivatedJob) -> JobFuture {
+        #[cfg(feature = "metrics")]
+        let handler_started = std::time::Instant::now();
+        let traced_job = aj.clone();
+        let slf = self.clone();
+        let job = self
+            .process_job(aj.clone())
+            .then(move |result| {
+                drop(slot);
+                slf.counters.job_handled(result.as_ref().ok());
+                #[cfg(feature = "metrics")]
+                slf.record(|metrics, job_type| {
+                    let job_result = result.as_ref().ok();
+                    metrics.job_handled(job_type, job_result, handler_started.elapsed())
+                });
+                match result {
+                    Err(panic_message) => {
+                        if let Some(on_panic) = &slf.on_panic {
+                            on_panic(&aj, &panic_message);
+                        }
+                        slf.report_panic(aj, panic_message)
+                    }
+                    Ok(job_result) => slf.report(aj, job_result),
+                }
+            })
+            .then(|_| futures::future::ready(()))
+            .boxed();
+        crate::instrument::job_future(&traced_job, job)
+    }
+
+    /// Run the middleware and the handler on `activated_job`. Fails with the panic message if the
+    /// handler or a middleware hook panics.
+    fn process_job(
+        self: Arc<Self>,
+        activated_job: ActivatedJob,
+    ) -> Pin<Box<dyn Future<Output = Result<JobResult, String>> + Send>> {
+        let mut job = activated_job.clone();
+        for (n, middleware) in self.middleware.iter().enumerate() {
+            match catch_panic(|| middleware.before_job(job)) {
+                Ok(Ok(passed_on)) => job = passed_on,
+                // only the middleware that saw the job wraps its result
+                Ok(Err(job_result)) => {
+                    let job_result = self.after_job(&activated_job, job_result, n + 1);
+                    return futures::future::ready(job_result).boxed();
+                }
+                Err(panic_message) => {
+                    self.on_failure(&activated_job, &panic_message, n);
+                    return futures::future::err(panic_message).boxed();
+                }
+            }
+        }
+        let wrapping = self.middleware.len();
+        self.job_handler
+            .process_job(job)
+            .map(move |result| match result {
+                Ok(job_result) => self.after_job(&activated_job, job_result, wrapping),
+                Err(panic_message) => {
+                    self.on_failure(&activated_job, &panic_message, wrapping);
+                    Err(panic_message)
                 }
             })
             .boxed()
     }
+
+    /// Run the `after_completion` or `on_failure` hooks of the first `wrapping` middleware on a
+    /// job result, innermost first. A job completed with variables that are not a JSON object is
+    /// failed instead, before the `on_failure` hooks run. Fails with the panic message if a hook
+    /// panics.
+    fn after_job(
+        &self,
+        activated_job: &ActivatedJob,
+        job_result: JobResult,
+        wrapping: usize,
+    ) -> Result<JobResult, String> {
+        match job_result {
+            JobResult::Complete { mut variables } => {
+                for (n, middleware) in self.middleware[..wrapping].iter().enumerate().rev() {
+                    match catch_panic(|| middleware.after_completion(activated_job, variables)) {
+                        Ok(changed) => variables = changed,
+                        Err(panic_message) => {
+                            self.on_failure(activated_job, &panic_message, n);
+                            return Err(panic_message);
+                        }
+                    }
+                }
+                // an empty document means no variables to the gateway
+                let variables = variables.filter(|variables| !variables.trim().is_empty());
+                // the gateway would reject the completion, fail the job with a message that says why
+                if let Some(Err(e)) = variables
+                    .as_ref()
+                    .map(|variables| check_variables(variables))
+                {
+                    let message = format!("the job handler returned invalid variables: {}", e);
+                    return self.after_job(activated_job, JobResult::fail(message), wrapping);
+                }
+                Ok(JobResult::Complete { variables })
+            }
+            JobResult::Fail {
+                error_message,
+                retries,
+            } => {
+                let message = error_message.as_ref().map(String::as_str).unwrap_or("");
+                match self.on_failure(activated_job, message, wrapping) {
+                    Some(panic_message) => Err(panic_message),
+                    None => Ok(JobResult::Fail {
+                        error_message,
+                        retries,
+                    }),
+                }
+            }
+            JobResult::NoAction => Ok(JobResult::NoAction),
+        }
+    }
+
+    /// Run the `on_failure` hooks of the first `wrapping` middleware, innermost first. A hook that
+    /// panics does not keep the others from running; resolves to the message of the first panic.
+    fn on_failure(
+        &self,
+        activated_job: &ActivatedJob,
+        error_message: &str,
+        wrapping: usize,
+    ) -> Option<String> {
+        self.middleware[..wrapping]
+            .iter()
+            .rev()
+            .fold(None, |panicked, middleware| {
+                let hook = catch_panic(|| middleware.on_failure(activated_job, error_message));
+                panicked.or(hook.err())
+            })
+    }
+
+    /// Report a job whose handler panicked according to the panic option.
+    fn report_panic(
+        &self,
+        activated_job: ActivatedJob,
+        panic_message: String,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let job_result = match self.panic_option {
+            PanicOption::FailJobOnPanic => JobResult::fail(panic_message),
+            PanicOption::RetryJobOnPanic => {
+                JobResult::fail_with_retries(panic_message, activated_job.retries)
+            }
+            PanicOption::DoNothingOnPanic => return futures::future::ok(()).boxed(),
+        };
+        self.report(activated_job, job_result)
+    }
+
+    /// Report the result of a job. Calls the `on_retries_exhausted` callback once a failure that
+    /// leaves the job without retries is reported.
+    fn report(
+        &self,
+        activated_job: ActivatedJob,
+        job_result: JobResult,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let exhausted = match (&self.on_retries_exhausted, &job_result) {
+            (
+                Some(on_retries_exhausted),
+                JobResult::Fail {
+                    error_message,
+                    retries,
+                },
+            ) if retries.unwrap_or(activated_job.retries - 1) <= 0 => Some((
+                on_retries_exhausted.clone(),
+                activated_job.clone(),
+                error_message.clone().unwrap_or_default(),
+            )),
+            _ => None,
+        };
+        let report = self.job_client.report_status(activated_job, job_result);
+        match exhausted {
+            None => report,
+            Some((on_retries_exhausted, activated_job, error_message)) => report
+                .inspect(move |result| {
+                    if result.is_ok() {
+                        on_retries_exhausted(&activated_job, &error_message);
+                    }
+                })
+                .boxed(),
+        }
+    }
+
+    /// Record into the metrics of the client, if it has any.
+    #[cfg(feature = "metrics")]
+    fn record<F: FnOnce(&crate::Metrics, &str)>(&self, record: F) {
+        if let Some(metrics) = self.client.metrics() {
+            record(metrics, &self.job_type);
+        }
+    }
 }
 
 /// A worker will activate zeebe jobs and us the job handler to process those jobs concurrently.
+///
+/// Cloning a worker is cheap and every clone shares the same job handler and the same count of
+/// running jobs. It is safe to call `activate_and_process_jobs` concurrently on several clones;
+/// each activation claims its share of the free capacity atomically, so the clones together never
+/// run more than `max_amount` jobs and no job is handed to the handler twice.
+///
+/// `max_amount` bounds the number of handler futures, not just the size of an activation: every
+/// activated job holds one of the worker's `max_amount` slots until its handler is done and its
+/// result is reported, and activations only ask for free slots. A burst of activations can
+/// therefore never run more handlers at once than the worker allows.
 #[derive(Clone)]
 pub struct JobWorker {
     job_internal: Arc<JobInternal>,
 }
 
 impl JobWorker {
-    pub fn new<F>(
+    /// Configure a worker for `job_type` with named settings and defaults, see
+    /// `JobWorkerBuilder`.
+    pub fn builder<G, S>(client: G, job_type: S) -> JobWorkerBuilder
+    where
+        G: ZeebeGateway + Send + Sync + 'static,
+        S: Into<String>,
+    {
+        JobWorkerBuilder::new(client, job_type)
+    }
+
+    /// Create a worker that activates jobs through `client`, usually a `Client`. Any other
+    /// `ZeebeGateway`, like `testing::MockClient`, works as well.
+    pub fn new<G, F>(
         worker: String,
         job_type: String,
         timeout: i64,
         max_amount: u16,
         panic_option: PanicOption,
-        client: Client,
+        client: G,
+        job_handler: F,
+    ) -> Self
+    where
+        G: ZeebeGateway + Send + Sync + 'static,
+        F: Fn(ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let settings = WorkerSettings::new(worker, job_type, timeout, max_amount, panic_option);
+        JobWorker::with_settings(Arc::new(client), settings, job_handler)
+    }
+
+    /// Create a worker from the settings collected by a `JobWorkerBuilder`.
+    pub(crate) fn with_settings<F>(
+        client: Arc<dyn ZeebeGateway + Send + Sync>,
+        settings: WorkerSettings,
         job_handler: F,
     ) -> Self
     where
@@ -136,21 +591,624 @@ impl JobWorker {
         let job_internal = Arc::new(JobInternal {
             job_client: JobClient::new(Reporter::new(client.clone())),
             job_handler: JobHandler::new(Arc::new(job_handler)),
-            job_count: AtomicUsize::new(0),
-            max_concurrent_jobs: max_amount as _,
+            job_count: Arc::new(AtomicUsize::new(0)),
+            slot_wakers: Mutex::new(Vec::new()),
+            max_concurrent_jobs: settings.max_jobs as _,
             client,
-            worker_name: worker,
+            worker_name: settings.worker_name,
+            job_type: settings.job_type,
+            timeout: settings.timeout,
+            request_timeout: settings.request_timeout.map_or(0, saturating_millis),
+            fetch_variables: settings.fetch_variables,
+            max_idle_backoff: settings.max_idle_backoff,
+            start_delay: settings.start_delay,
+            panic_option: settings.panic_option,
+            on_panic: settings.on_panic,
+            on_retries_exhausted: settings.on_retries_exhausted,
+            middleware: settings.middleware,
+            rate_limiter: settings
+                .max_jobs_per_second
+                .map(|max_jobs_per_second| Arc::new(RateLimiter::new(max_jobs_per_second))),
+            counters: Arc::new(Counters::default()),
+        });
+
+        JobWorker { job_internal }
+    }
+
+    /// Like `JobWorker::new`, but the handler also receives a `CompletionContext` to accumulate
+    /// output variables in. See `CompletionContext` for how the context is combined with the
+    /// returned `JobResult`.
+    pub fn new_with_context<G, F>(
+        worker: String,
+        job_type: String,
+        timeout: i64,
+        max_amount: u16,
+        panic_option: PanicOption,
+        client: G,
+        job_handler: F,
+    ) -> Self
+    where
+        G: ZeebeGateway + Send + Sync + 'static,
+        F: Fn(ActivatedJob, CompletionContext) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        JobWorker::new(
+            worker,
             job_type,
             timeout,
+            max_amount,
             panic_option,
-        });
+            client,
+            with_context(job_handler),
+        )
+    }
 
-        JobWorker { job_internal }
+    /// Like `JobWorker::new`, but jobs are processed by a `Handler` instead of a closure.
+    pub fn new_with_handler<G, H>(
+        worker: String,
+        job_type: String,
+        timeout: i64,
+        max_amount: u16,
+        panic_option: PanicOption,
+        client: G,
+        handler: H,
+    ) -> Self
+    where
+        G: ZeebeGateway + Send + Sync + 'static,
+        H: Handler + 'static,
+    {
+        JobWorker::new(
+            worker,
+            job_type,
+            timeout,
+            max_amount,
+            panic_option,
+            client,
+            move |activated_job| handler.handle(activated_job).boxed(),
+        )
+    }
+
+    /// The number of jobs whose handler is running or whose result is being reported, including
+    /// the slots claimed by activations that are still waiting for the gateway. The next
+    /// activation requests at most `max_amount` minus this many jobs.
+    pub fn jobs_in_flight(&self) -> usize {
+        self.job_internal.job_count.load(Ordering::SeqCst)
+    }
+
+    /// Statistics of this worker and all of its clones, see `WorkerStats`.
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats::new(
+            self.job_internal.counters.clone(),
+            self.job_internal.job_count.clone(),
+        )
     }
 
     /// Activates a batch of jobs and processes each job with the job handler. Will not activate
-    /// more jobs that `max_concurrent_jobs - current_job_count`.
+    /// more jobs that `max_concurrent_jobs - current_job_count`. If the worker is already at
+    /// capacity no request is sent to the gateway and the future resolves immediately.
     pub fn activate_and_process_jobs(self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-        self.job_internal.activate_and_process_jobs()
+        self.job_internal
+            .activate_and_process_jobs()
+            .map(|_| ())
+            .boxed()
+    }
+
+    /// Run the worker in a managed polling loop. The worker processes the activated jobs
+    /// alongside the loop and polls again as soon as it has a free slot, asking for as many jobs
+    /// as there are free slots, so a slow job never holds back the activation of others. While
+    /// activations come back empty (or fail) the
+    /// worker backs off, starting at `poll_interval` and doubling up to eight times that, or up to
+    /// `JobWorkerBuilder::max_idle_backoff`, and goes back to polling immediately once jobs show
+    /// up. Activations rejected because of backpressure count as failed, so an overloaded broker
+    /// is polled less often.
+    ///
+    /// The first poll waits for the `poll_offset` and the `start_jitter` of the
+    /// `JobWorkerBuilder`, if they are set.
+    ///
+    /// The returned future runs until the `WorkerHandle` is used to stop or shut down the worker.
+    pub fn run(
+        self,
+        poll_interval: Duration,
+    ) -> (WorkerHandle, Pin<Box<dyn Future<Output = ()> + Send>>) {
+        let worker_handle = WorkerHandle::new();
+        let drained = worker_handle.drained.clone();
+        let run = self
+            .run_until(poll_interval, Duration::from_secs(0), &worker_handle)
+            .map(move |_| drained.raise())
+            .boxed();
+        (worker_handle, run)
+    }
+
+    /// Run the polling loop until the handle is stopped or shut down. The first poll waits for
+    /// `start_offset` on top of the start delay of the worker.
+    pub(crate) fn run_until(
+        self,
+        poll_interval: Duration,
+        start_offset: Duration,
+        worker_handle: &WorkerHandle,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let job_internal = self.job_internal;
+        let idle_backoff = idle_backoff(poll_interval, job_internal.max_idle_backoff);
+        let start_delay =
+            polling::saturating_add(start_offset, job_internal.start_delay.next_delay());
+        job_internal.poll(idle_backoff, start_delay, worker_handle)
+    }
+}
+
+/// Adapt a handler that takes a `CompletionContext` to a plain handler.
+fn with_context<F>(
+    job_handler: F,
+) -> impl Fn(ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>> + Send + Sync + 'static
+where
+    F: Fn(ActivatedJob, CompletionContext) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+        + Send
+        + Sync
+        + 'static,
+{
+    move |activated_job| {
+        let context = CompletionContext::new();
+        job_handler(activated_job, context.clone())
+            .map(move |job_result| context.finish(job_result))
+            .boxed()
+    }
+}
+
+/// The idle backoff of a polling loop, capped at `max_idle_backoff` if it is set.
+fn idle_backoff(poll_interval: Duration, max_idle_backoff: Option<Duration>) -> IdleBackoff {
+    let idle_backoff = IdleBackoff::new(poll_interval);
+    match max_idle_backoff {
+        Some(max_idle_backoff) => idle_backoff.max_delay(max_idle_backoff),
+        None => idle_backoff,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock_gateway::MockGateway;
+    use crate::testing::MockClient;
+    use crate::{
+        ActivateJobs, ActivatedJob, Error, JobResult, JobWorker, PanicOption, WorkerHandle,
+    };
+    use futures::channel::oneshot;
+    use futures::future::Shared;
+    use futures::{Future, FutureExt, StreamExt};
+    use std::collections::BTreeMap;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// A handler that completes its jobs right away.
+    pub(super) fn complete(_: ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>> {
+        futures::future::ready(JobResult::Complete { variables: None }).boxed()
+    }
+
+    /// A job that completes once `released` fires.
+    pub(super) fn blocked_job(
+        released: &Shared<oneshot::Receiver<()>>,
+    ) -> Pin<Box<dyn Future<Output = JobResult> + Send>> {
+        released
+            .clone()
+            .map(|_| JobResult::Complete { variables: None })
+            .boxed()
+    }
+
+    /// Resolves once `condition` holds, checking it every millisecond.
+    pub(super) fn wait_until<C>(condition: C) -> impl Future<Output = ()>
+    where
+        C: Fn() -> bool,
+    {
+        futures::stream::repeat(())
+            .then(|_| futures_timer::Delay::new(Duration::from_millis(1)))
+            .filter(move |_| futures::future::ready(condition()))
+            .into_future()
+            .map(|_| ())
+    }
+
+    /// A `payment-service` worker that runs at most `max_amount` jobs at once with `job_handler`.
+    fn worker_with<F>(gateway: &MockGateway, max_amount: u16, job_handler: F) -> JobWorker
+    where
+        F: Fn(ActivatedJob) -> Pin<Box<dyn Future<Output = JobResult> + Send>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        JobWorker::new(
+            "rusty-worker".to_string(),
+            "payment-service".to_string(),
+            10,
+            max_amount,
+            PanicOption::FailJobOnPanic,
+            gateway.client(),
+            job_handler,
+        )
+    }
+
+    fn worker(gateway: &MockGateway, max_amount: u16) -> JobWorker {
+        worker_with(gateway, max_amount, complete)
+    }
+
+    #[test]
+    fn complete_with_serializes_objects() {
+        let mut variables = BTreeMap::new();
+        variables.insert("orderId", 10);
+        assert_eq!(
+            JobResult::complete_with(&variables).unwrap(),
+            JobResult::Complete {
+                variables: Some(r#"{"orderId":10}"#.to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn complete_with_rejects_non_objects() {
+        match JobResult::complete_with(&vec![1, 2, 3]) {
+            Err(Error::VariablesNotAnObject(kind)) => assert_eq!(kind, "array"),
+            other => panic!(
+                "expected non-object variables to be rejected, got {:?}",
+                other
+            ),
+        }
+    }
+
+    #[test]
+    fn completes_with_context_variables() {
+        let gateway = MockGateway::with_jobs(1);
+        let worker = JobWorker::new_with_context(
+            "rusty-worker".to_string(),
+            "payment-service".to_string(),
+            10,
+            1,
+            PanicOption::FailJobOnPanic,
+            gateway.client(),
+            |activated_job, context| {
+                context.set("orderId", &activated_job.key).unwrap();
+                futures::future::ready(JobResult::Complete { variables: None }).boxed()
+            },
+        );
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let completed = gateway.completed.lock().unwrap();
+        assert_eq!(completed[0].variables, r#"{"orderId":0}"#);
+    }
+
+    #[test]
+    fn sends_request_timeout() {
+        let gateway = MockGateway::with_jobs(1);
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .request_timeout(Duration::from_secs(30))
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let requests = gateway.activate_requests.lock().unwrap();
+        assert_eq!(requests[0].requestTimeout, 30_000);
+
+        // a timeout beyond the range of the request is capped instead of wrapping
+        let gateway = MockGateway::with_jobs(1);
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .request_timeout(Duration::new(u64::max_value(), 0))
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let requests = gateway.activate_requests.lock().unwrap();
+        assert_eq!(requests[0].requestTimeout, i64::max_value());
+        let activate_jobs = ActivateJobs::new("rusty-worker", "payment-service", 10, 1)
+            .request_timeout(Duration::new(u64::max_value(), 0));
+        assert_eq!(activate_jobs.request_timeout, i64::max_value());
+    }
+
+    #[test]
+    fn run_processes_jobs_until_stopped() {
+        let gateway = MockGateway::with_jobs(5);
+        let (worker_handle, run) = worker(&gateway, 2).run(Duration::from_millis(1));
+        let stopper = gateway.clone();
+        let wait_for_jobs = wait_until(move || stopper.completed_keys().len() == 5)
+            .map(move |_| worker_handle.stop());
+        futures::executor::block_on(futures::future::join(run, wait_for_jobs));
+        assert_eq!(gateway.completed_keys(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn a_slow_job_does_not_hold_back_other_jobs() {
+        let gateway = MockGateway::with_jobs(3);
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let worker = worker_with(&gateway, 2, move |activated_job| match activated_job.key {
+            0 => blocked_job(&released),
+            _ => complete(activated_job),
+        });
+        let (worker_handle, run) = worker.run(Duration::from_millis(1));
+        let observer = gateway.clone();
+        let stop = wait_until(move || observer.completed_keys() == vec![1, 2]).then(move |_| {
+            // the third job was activated into the slot of the second while the first runs
+            release.send(()).unwrap();
+            worker_handle.shutdown()
+        });
+        futures::executor::block_on(futures::future::join(run, stop));
+        assert_eq!(gateway.completed_keys(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn huge_start_delays_and_poll_intervals_wait_until_stopped() {
+        let gateway = MockGateway::with_jobs(1);
+        let huge = Duration::new(u64::max_value(), 0);
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .poll_offset(huge)
+            .start_jitter(huge)
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        let worker_handle = WorkerHandle::new();
+        let run = worker.run_until(huge, huge, &worker_handle);
+        let stopper = worker_handle.clone();
+        let stop = futures_timer::Delay::new(Duration::from_millis(5)).map(move |_| stopper.stop());
+        futures::executor::block_on(futures::future::join(run, stop));
+        assert!(gateway.requested_amounts().is_empty());
+
+        // without a start delay the worker polls once and then waits out the huge interval
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        let worker_handle = WorkerHandle::new();
+        let run = worker.run_until(huge, Duration::from_secs(0), &worker_handle);
+        let stopper = worker_handle.clone();
+        let stop = futures_timer::Delay::new(Duration::from_millis(5)).map(move |_| stopper.stop());
+        futures::executor::block_on(futures::future::join(run, stop));
+        assert_eq!(gateway.completed_keys(), vec![0]);
+    }
+
+    #[test]
+    fn shutdown_drains_in_flight_jobs() {
+        let gateway = MockGateway::with_jobs(2);
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let worker = worker_with(&gateway, 2, move |_| blocked_job(&released));
+        let (worker_handle, run) = worker.run(Duration::from_millis(1));
+        let activated = gateway.clone();
+        let shutdown =
+            wait_until(move || !activated.requested_amounts().is_empty()).then(move |_| {
+                let drained = worker_handle.shutdown();
+                // the handlers are still blocked, so nothing may have been reported yet
+                let completed_before_release = gateway.completed_keys();
+                release.send(()).unwrap();
+                drained.map(move |_| (completed_before_release, gateway))
+            });
+        let (_, (completed_before_release, gateway)) =
+            futures::executor::block_on(futures::future::join(run, shutdown));
+        assert!(completed_before_release.is_empty());
+        assert_eq!(gateway.completed_keys(), vec![0, 1]);
+        assert_eq!(gateway.requested_amounts(), vec![2]);
+    }
+
+    #[test]
+    fn concurrent_activations_from_clones_do_not_exceed_max_jobs() {
+        let gateway = MockGateway::with_jobs(10);
+        let worker = worker(&gateway, 3);
+        let first = worker.clone().activate_and_process_jobs();
+        let second = worker.clone().activate_and_process_jobs();
+        futures::executor::block_on(futures::future::join(first, second));
+        assert_eq!(gateway.requested_amounts(), vec![3]);
+        assert_eq!(gateway.completed_keys(), vec![0, 1, 2]);
+        assert_eq!(worker.job_internal.job_count.load(Ordering::SeqCst), 0);
+    }
+
+    fn panicking_worker(gateway: &MockGateway, panic_option: PanicOption) -> JobWorker {
+        JobWorker::new(
+            "rusty-worker".to_string(),
+            "payment-service".to_string(),
+            10,
+            1,
+            panic_option,
+            gateway.client(),
+            |_| panic!("card declined"),
+        )
+    }
+
+    #[test]
+    fn fails_jobs_completed_with_invalid_variables() {
+        let client = MockClient::new();
+        let variables: BTreeMap<String, i32> = BTreeMap::new();
+        client.add_job(1, "payment-service", &variables).unwrap();
+        client.add_job(2, "payment-service", &variables).unwrap();
+        let worker = JobWorker::builder(client.clone(), "payment-service").build(|job| {
+            // an empty document completes the job without variables
+            let variables = if job.key == 1 { "[1, 2, 3]" } else { " " };
+            let variables = Some(variables.to_string());
+            futures::future::ready(JobResult::Complete { variables }).boxed()
+        });
+        let stats = worker.stats();
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert_eq!(client.failed()[0].job_key, 1);
+        assert_eq!(
+            client.failed()[0].error_message,
+            "the job handler returned invalid variables: Variables must be a JSON object, got array"
+        );
+        assert_eq!(client.completed()[0].job_key, 2);
+        assert_eq!(client.completed()[0].variables, None);
+        // the stats see the failure, not the completion the handler returned
+        assert_eq!((stats.jobs_completed(), stats.jobs_failed()), (1, 1));
+    }
+
+    #[test]
+    fn reports_jobs_without_retries_left() {
+        let client = MockClient::new();
+        let variables: BTreeMap<String, i32> = BTreeMap::new();
+        client.add_job(1, "payment-service", &variables).unwrap();
+        client.add_job(2, "payment-service", &variables).unwrap();
+        client.push_job(ActivatedJob {
+            key: 3,
+            field_type: "payment-service".to_string(),
+            custom_headers: "{}".to_string(),
+            retries: 1,
+            variables: "{}".to_string(),
+            ..Default::default()
+        });
+        let exhausted = Arc::new(Mutex::new(Vec::new()));
+        let reported = exhausted.clone();
+        let worker = JobWorker::builder(client, "payment-service")
+            .on_retries_exhausted(move |job, error_message| {
+                reported
+                    .lock()
+                    .unwrap()
+                    .push((job.key, error_message.to_string()))
+            })
+            .build(|job| {
+                let job_result = match job.key {
+                    1 => JobResult::fail_with_retries("card declined", 0),
+                    2 => JobResult::fail("try again"),
+                    _ => JobResult::fail("card expired"),
+                };
+                futures::future::ready(job_result).boxed()
+            });
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let mut exhausted = exhausted.lock().unwrap().clone();
+        exhausted.sort();
+        assert_eq!(
+            exhausted,
+            vec![
+                (1, "card declined".to_string()),
+                (3, "card expired".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_panicking_jobs_with_the_panic_message() {
+        let gateway = MockGateway::with_jobs(1);
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let alerts = panics.clone();
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .max_jobs(1)
+            .on_panic(move |activated_job, panic_message| {
+                let alert = (activated_job.key, panic_message.to_string());
+                alerts.lock().unwrap().push(alert);
+            })
+            .build(|_| panic!("card declined"));
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        let failed = gateway.failed.lock().unwrap();
+        assert_eq!(failed[0].errorMessage, "card declined");
+        assert_eq!(failed[0].retries, 2);
+        assert_eq!(
+            *panics.lock().unwrap(),
+            vec![(0, "card declined".to_string())]
+        );
+    }
+
+    #[test]
+    fn retries_panicking_jobs_without_decrementing() {
+        let gateway = MockGateway::with_jobs(1);
+        let worker = panicking_worker(&gateway, PanicOption::RetryJobOnPanic);
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert_eq!(gateway.failed.lock().unwrap()[0].retries, 3);
+
+        let gateway = MockGateway::with_jobs(1);
+        let worker = panicking_worker(&gateway, PanicOption::DoNothingOnPanic);
+        futures::executor::block_on(worker.activate_and_process_jobs());
+        assert!(gateway.failed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn handlers_never_run_more_than_max_jobs_at_once() {
+        let gateway = MockGateway::with_jobs(10);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(Mutex::new(0));
+        let (counted, observed) = (running.clone(), peak.clone());
+        let worker = worker_with(&gateway, 3, move |_| {
+            let now_running = counted.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut peak = observed.lock().unwrap();
+            *peak = std::cmp::max(*peak, now_running);
+            let counted = counted.clone();
+            futures_timer::Delay::new(Duration::from_millis(5))
+                .map(move |_| {
+                    counted.fetch_sub(1, Ordering::SeqCst);
+                    JobResult::Complete { variables: None }
+                })
+                .boxed()
+        });
+        // a burst of activations from several clones at once
+        let activations = (0..4).map(|_| worker.clone().activate_and_process_jobs());
+        futures::executor::block_on(futures::future::join_all(activations));
+        let (worker_handle, run) = worker.run(Duration::from_millis(1));
+        let stopper = gateway.clone();
+        let wait_for_jobs = wait_until(move || stopper.completed_keys().len() == 10)
+            .map(move |_| worker_handle.stop());
+        futures::executor::block_on(futures::future::join(run, wait_for_jobs));
+        assert_eq!(*peak.lock().unwrap(), 3);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn activations_request_only_the_allowed_rate() {
+        let gateway = MockGateway::with_jobs(6);
+        let worker = JobWorker::builder(gateway.client(), "payment-service")
+            .max_jobs(10)
+            .max_jobs_per_second(4)
+            .build(|_| futures::future::ready(JobResult::Complete { variables: None }).boxed());
+        futures::executor::block_on(worker.clone().activate_and_process_jobs());
+        // the four permits of the first second are used up, the next activation waits for one
+        futures::executor::block_on(worker.clone().activate_and_process_jobs());
+        assert_eq!(gateway.requested_amounts(), vec![4, 1]);
+        assert_eq!(worker.jobs_in_flight(), 0);
+    }
+
+    #[test]
+    fn activations_request_only_the_free_capacity() {
+        let gateway = MockGateway::with_jobs(2);
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let worker = worker_with(&gateway, 3, move |_| blocked_job(&released));
+        let first = worker.clone().activate_and_process_jobs();
+        let observer = worker.clone();
+        let second = futures_timer::Delay::new(Duration::from_millis(5)).then(move |_| {
+            let in_flight = observer.jobs_in_flight();
+            let second = observer.activate_and_process_jobs();
+            release.send(()).unwrap();
+            second.map(move |_| in_flight)
+        });
+        let (_, in_flight) = futures::executor::block_on(futures::future::join(first, second));
+        assert_eq!(in_flight, 2);
+        assert_eq!(gateway.requested_amounts(), vec![3, 1]);
+        assert_eq!(worker.jobs_in_flight(), 0);
+    }
+
+    #[test]
+    fn the_polling_loop_requests_the_slots_left_by_a_running_job() {
+        let gateway = MockGateway::with_jobs(1);
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let worker = worker_with(&gateway, 3, move |_| blocked_job(&released));
+        let (worker_handle, run) = worker.run(Duration::from_millis(1));
+        let (activations, completions) = (gateway.clone(), gateway.clone());
+        let stop = wait_until(move || activations.requested_amounts().len() >= 2).then(move |_| {
+            // the job is blocked until it is released, so it was running during the second
+            // activation
+            let completed_before_release = completions.completed_keys();
+            release.send(()).unwrap();
+            worker_handle
+                .shutdown()
+                .map(move |_| completed_before_release)
+        });
+        let (_, completed_before_release) =
+            futures::executor::block_on(futures::future::join(run, stop));
+        assert!(completed_before_release.is_empty());
+        assert_eq!(&gateway.requested_amounts()[..2], &[3, 2]);
+        assert_eq!(gateway.completed_keys(), vec![0]);
+    }
+
+    #[test]
+    fn unfilled_capacity_is_released() {
+        let gateway = MockGateway::with_jobs(1);
+        let worker = worker(&gateway, 3);
+        futures::executor::block_on(worker.clone().activate_and_process_jobs());
+        assert_eq!(worker.job_internal.job_count.load(Ordering::SeqCst), 0);
+        futures::executor::block_on(worker.clone().activate_and_process_jobs());
+        assert_eq!(gateway.requested_amounts(), vec![3, 3]);
+        assert_eq!(gateway.completed_keys(), vec![0]);
+    }
+
+    #[test]
+    fn dropped_activation_releases_capacity() {
+        let gateway = MockGateway::with_jobs(0);
+        let worker = worker(&gateway, 3);
+        drop(worker.clone().activate_and_process_jobs());
+        assert_eq!(worker.job_internal.job_count.load(Ordering::SeqCst), 0);
+        futures::executor::block_on(worker.clone().activate_and_process_jobs());
+        assert_eq!(gateway.requested_amounts(), vec![3, 3]);
     }
 }
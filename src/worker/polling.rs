@@ -0,0 +1,360 @@
+use futures::task::{Context, Poll, Waker};
+use futures::{Future, FutureExt, StreamExt};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// By default the idle backoff grows up to this factor of the poll interval.
+const MAX_IDLE_BACKOFF_FACTOR: u32 = 8;
+
+/// The largest duration, where the saturating operations on durations end.
+const MAX_DURATION: Duration = Duration::from_secs(u64::max_value());
+
+/// Computes the delay before the next poll of a managed polling loop.
+#[derive(Clone, Debug)]
+pub struct IdleBackoff {
+    poll_interval: Duration,
+    max_delay: Duration,
+    current: Duration,
+}
+
+impl IdleBackoff {
+    pub fn new(poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            max_delay: saturating_mul(poll_interval, MAX_IDLE_BACKOFF_FACTOR),
+            current: poll_interval,
+        }
+    }
+
+    /// Cap the backoff at `max_delay` instead of eight times the poll interval. The cap is never
+    /// below the poll interval.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = std::cmp::max(max_delay, self.poll_interval);
+        self
+    }
+
+    /// The delay before the next poll, given the number of jobs the last poll activated.
+    pub fn next_delay(&mut self, activated: usize) -> Duration {
+        if activated > 0 {
+            self.current = self.poll_interval;
+            Duration::from_secs(0)
+        } else {
+            let delay = self.current;
+            self.current = std::cmp::min(saturating_mul(self.current, 2), self.max_delay);
+            delay
+        }
+    }
+}
+
+/// How long a managed polling loop waits before its first poll: a fixed `offset` plus a random
+/// delay of up to `jitter`, so workers that start together do not poll in lockstep.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StartDelay {
+    pub offset: Duration,
+    pub jitter: Duration,
+}
+
+impl StartDelay {
+    /// Draw the delay before the first poll.
+    pub fn next_delay(&self) -> Duration {
+        let max_jitter_nanos = u128::from(u64::max_value() - 1);
+        let jitter_nanos = std::cmp::min(self.jitter.as_nanos(), max_jitter_nanos) as u64;
+        let jitter = crate::backpressure::random() % (jitter_nanos + 1);
+        saturating_add(self.offset, Duration::from_nanos(jitter))
+    }
+}
+
+/// Add two durations, saturating at the largest duration instead of overflowing.
+pub fn saturating_add(a: Duration, b: Duration) -> Duration {
+    a.checked_add(b).unwrap_or(MAX_DURATION)
+}
+
+/// Multiply a duration, saturating at the largest duration instead of overflowing.
+pub fn saturating_mul(duration: Duration, factor: u32) -> Duration {
+    duration.checked_mul(factor).unwrap_or(MAX_DURATION)
+}
+
+/// Wait for `duration`. A delay that ends beyond what the clock can represent never ends, instead
+/// of overflowing the timer.
+pub fn sleep(duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    match Instant::now().checked_add(duration) {
+        Some(_) => futures_timer::Delay::new(duration).boxed(),
+        None => futures::future::pending().boxed(),
+    }
+}
+
+/// A flag that can be raised once and awaited by any number of futures.
+#[derive(Clone, Debug, Default)]
+pub struct Signal {
+    inner: Arc<SignalInner>,
+}
+
+#[derive(Debug, Default)]
+struct SignalInner {
+    raised: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Signal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn raise(&self) {
+        self.inner.raised.store(true, Ordering::SeqCst);
+        for waker in self.inner.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.inner.raised.load(Ordering::SeqCst)
+    }
+
+    /// A future that resolves once the signal is raised.
+    pub fn raised(&self) -> SignalRaised {
+        SignalRaised {
+            signal: self.clone(),
+        }
+    }
+}
+
+pub struct SignalRaised {
+    signal: Signal,
+}
+
+impl Future for SignalRaised {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.signal.is_raised() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.signal.inner.wakers.lock().unwrap();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        // the signal may have been raised before the waker was registered
+        if self.signal.is_raised() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A handle to a worker running in a managed polling loop.
+#[derive(Clone, Debug)]
+pub struct WorkerHandle {
+    pub(crate) stop: Signal,
+    pub(crate) shutdown: Signal,
+    pub(crate) drained: Signal,
+}
+
+impl WorkerHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            stop: Signal::new(),
+            shutdown: Signal::new(),
+            drained: Signal::new(),
+        }
+    }
+
+    /// Stop the polling loop immediately. Jobs that are still being processed are dropped and
+    /// will be activated again once their timeout expires.
+    pub fn stop(&self) {
+        self.stop.raise();
+    }
+
+    /// Shut the worker down gracefully: no new jobs are activated, the activation that is in
+    /// progress (which may be long polling) finishes, and the handlers of all activated jobs run
+    /// to completion and report their results. The returned future resolves once the worker is
+    /// fully drained. The future returned by `run` must still be driven for this to happen.
+    pub fn shutdown(&self) -> impl Future<Output = ()> + Send {
+        self.shutdown.raise();
+        self.drained.raised()
+    }
+}
+
+/// Run a polling loop until the handle is stopped or shut down. `activate` activates one round of
+/// jobs and resolves to the number of activated jobs, which drives the idle backoff. The first
+/// poll waits for `start_delay`.
+pub(crate) fn poll_until<F>(
+    activate: F,
+    idle_backoff: IdleBackoff,
+    start_delay: Duration,
+    worker_handle: &WorkerHandle,
+) -> Pin<Box<dyn Future<Output = ()> + Send>>
+where
+    F: Fn() -> Pin<Box<dyn Future<Output = usize> + Send>> + Send + 'static,
+{
+    let shutdown = worker_handle.shutdown.clone();
+    let polling = futures::stream::unfold(idle_backoff, move |mut idle_backoff| {
+        if shutdown.is_raised() {
+            return futures::future::ready(None).left_future();
+        }
+        let shutdown = shutdown.clone();
+        activate()
+            .then(move |activated| {
+                let delay = idle_backoff.next_delay(activated);
+                // a shutdown cuts the idle delay short
+                futures::future::select(sleep(delay), shutdown.raised())
+                    .map(move |_| Some(((), idle_backoff)))
+            })
+            .right_future()
+    })
+    .for_each(|_| futures::future::ready(()));
+    // a shutdown cuts the start delay short as well
+    let start = futures::future::select(sleep(start_delay), worker_handle.shutdown.raised());
+    let polling = start.then(move |_| polling).boxed();
+    futures::future::select(polling, worker_handle.stop.raised())
+        .map(|_| ())
+        .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{poll_until, IdleBackoff, Signal, StartDelay, WorkerHandle};
+    use futures::FutureExt;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn signal_wakes_waiting_futures() {
+        let signal = Signal::new();
+        let raiser = signal.clone();
+        let raise =
+            futures_timer::Delay::new(Duration::from_millis(1)).map(move |_| raiser.raise());
+        futures::executor::block_on(futures::future::join(signal.raised(), raise));
+        assert!(signal.is_raised());
+    }
+
+    #[test]
+    fn backs_off_while_idle_and_resets_on_jobs() {
+        let mut idle_backoff = IdleBackoff::new(Duration::from_millis(100));
+        let delays: Vec<u128> = [0, 0, 0, 0, 0, 3, 0]
+            .iter()
+            .map(|&activated| idle_backoff.next_delay(activated).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 800, 0, 100]);
+    }
+
+    #[test]
+    fn caps_the_backoff_at_the_configured_delay() {
+        let mut idle_backoff =
+            IdleBackoff::new(Duration::from_millis(100)).max_delay(Duration::from_millis(300));
+        let delays: Vec<u128> = (0..4)
+            .map(|_| idle_backoff.next_delay(0).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 300, 300]);
+        let mut idle_backoff =
+            IdleBackoff::new(Duration::from_millis(100)).max_delay(Duration::from_millis(10));
+        assert_eq!(idle_backoff.next_delay(0), idle_backoff.next_delay(0));
+    }
+
+    #[test]
+    fn saturates_huge_backoffs_instead_of_overflowing() {
+        let huge = Duration::new(u64::max_value(), 0);
+        let mut idle_backoff = IdleBackoff::new(huge);
+        assert_eq!(idle_backoff.next_delay(0), huge);
+        assert!(idle_backoff.next_delay(0) >= huge);
+
+        let mut idle_backoff = IdleBackoff::new(Duration::from_secs(1)).max_delay(huge);
+        // the delay doubles past the largest duration
+        let delays: Vec<Duration> = (0..100).map(|_| idle_backoff.next_delay(0)).collect();
+        assert_eq!(delays[99], huge);
+    }
+
+    #[test]
+    fn a_huge_poll_interval_waits_until_the_loop_is_stopped() {
+        let worker_handle = WorkerHandle::new();
+        let polls = Arc::new(Mutex::new(0));
+        let counted = polls.clone();
+        let run = poll_until(
+            move || {
+                *counted.lock().unwrap() += 1;
+                futures::future::ready(0).boxed()
+            },
+            IdleBackoff::new(Duration::new(u64::max_value(), 0)),
+            Duration::from_secs(0),
+            &worker_handle,
+        );
+        let stopper = worker_handle.clone();
+        let stop = futures_timer::Delay::new(Duration::from_millis(5)).map(move |_| stopper.stop());
+        futures::executor::block_on(futures::future::join(run, stop));
+        assert_eq!(*polls.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn start_delays_are_offset_and_jittered() {
+        let start_delay = StartDelay {
+            offset: Duration::from_millis(50),
+            jitter: Duration::from_millis(100),
+        };
+        for _ in 0..20 {
+            let delay = start_delay.next_delay();
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(150));
+        }
+        assert_eq!(StartDelay::default().next_delay(), Duration::from_secs(0));
+
+        let sub_millisecond = StartDelay {
+            offset: Duration::from_secs(0),
+            jitter: Duration::from_micros(500),
+        };
+        assert!(sub_millisecond.next_delay() <= Duration::from_micros(500));
+        let huge = StartDelay {
+            offset: Duration::new(u64::max_value(), 0),
+            jitter: Duration::new(u64::max_value(), 0),
+        };
+        assert!(huge.next_delay() >= Duration::new(u64::max_value(), 0));
+    }
+
+    #[test]
+    fn waits_for_the_start_delay_before_the_first_poll() {
+        let worker_handle = WorkerHandle::new();
+        let started = Instant::now();
+        let first_poll = Arc::new(Mutex::new(None));
+        let (polled, stopper) = (first_poll.clone(), worker_handle.clone());
+        let run = poll_until(
+            move || {
+                let mut polled = polled.lock().unwrap();
+                if polled.is_none() {
+                    *polled = Some(started.elapsed());
+                }
+                stopper.stop();
+                futures::future::ready(0).boxed()
+            },
+            IdleBackoff::new(Duration::from_millis(1)),
+            Duration::from_millis(20),
+            &worker_handle,
+        );
+        futures::executor::block_on(run);
+        assert!(first_poll.lock().unwrap().unwrap() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn a_shutdown_during_the_start_delay_ends_the_loop() {
+        let worker_handle = WorkerHandle::new();
+        let polls = Arc::new(Mutex::new(0));
+        let counted = polls.clone();
+        let run = poll_until(
+            move || {
+                *counted.lock().unwrap() += 1;
+                futures::future::ready(0).boxed()
+            },
+            IdleBackoff::new(Duration::from_millis(1)),
+            Duration::from_secs(60),
+            &worker_handle,
+        );
+        let shutdown = worker_handle.shutdown.clone();
+        let shut_down = futures_timer::Delay::new(Duration::from_millis(1)).map(move |_| {
+            shutdown.raise();
+        });
+        let started = Instant::now();
+        futures::executor::block_on(futures::future::join(run, shut_down));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(*polls.lock().unwrap(), 0);
+    }
+}
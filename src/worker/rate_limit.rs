@@ -0,0 +1,85 @@
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A token bucket that allows `max_per_second` jobs per second, in bursts of at most one second's
+/// worth. Shared by every clone of a worker.
+pub struct RateLimiter {
+    max_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(max_per_second: u32) -> Self {
+        let max_per_second = f64::from(std::cmp::max(max_per_second, 1));
+        Self {
+            max_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: max_per_second,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take up to `wanted` permits, as many as are available right now.
+    pub fn take(&self, wanted: usize) -> usize {
+        let mut bucket = self.refill();
+        let granted = std::cmp::min(bucket.tokens.floor() as usize, wanted);
+        bucket.tokens -= granted as f64;
+        granted
+    }
+
+    /// Give back permits that were taken but not used, e.g. because the gateway returned fewer
+    /// jobs than requested.
+    pub fn give_back(&self, permits: usize) {
+        let mut bucket = self.refill();
+        bucket.tokens = (bucket.tokens + permits as f64).min(self.max_per_second);
+    }
+
+    /// Wait until at least one permit is available and take up to `wanted` permits.
+    pub fn acquire(self: Arc<Self>, wanted: usize) -> Pin<Box<dyn Future<Output = usize> + Send>> {
+        let granted = self.take(wanted);
+        if granted > 0 || wanted == 0 {
+            return futures::future::ready(granted).boxed();
+        }
+        let missing = 1.0 - self.refill().tokens;
+        let wait = Duration::from_nanos((missing / self.max_per_second * 1e9) as u64);
+        futures_timer::Delay::new(wait)
+            .then(move |_| self.acquire(wanted))
+            .boxed()
+    }
+
+    fn refill(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        bucket.tokens = (bucket.tokens + elapsed * self.max_per_second).min(self.max_per_second);
+        bucket.refilled = now;
+        bucket
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RateLimiter;
+    use std::sync::Arc;
+
+    #[test]
+    fn grants_at_most_the_rate_and_takes_back_unused_permits() {
+        let rate_limiter = Arc::new(RateLimiter::new(5));
+        assert_eq!(rate_limiter.take(3), 3);
+        assert_eq!(rate_limiter.take(3), 2);
+        assert_eq!(rate_limiter.take(3), 0);
+        rate_limiter.give_back(2);
+        assert_eq!(rate_limiter.take(3), 2);
+        // waits for the bucket to refill
+        assert_eq!(futures::executor::block_on(rate_limiter.acquire(1)), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::JobResult;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// What a worker did since it was created, for health checks and status pages that do not need
+/// the full `metrics` integration. Get it with `JobWorker::stats`; the stats are shared by every
+/// clone of the worker and stay current while the worker runs.
+///
+/// ```rust,ignore
+/// let stats = worker.stats();
+/// let (handle, run) = worker.run(Duration::from_secs(1));
+/// // in a health endpoint
+/// let healthy = stats
+///     .last_successful_poll()
+///     .and_then(|polled| polled.elapsed().ok())
+///     .map_or(false, |since| since < Duration::from_secs(60));
+/// ```
+///
+/// The stats only share the counters of the worker, so holding on to them does not keep the
+/// client or the handler alive.
+#[derive(Clone)]
+pub struct WorkerStats {
+    counters: Arc<Counters>,
+    job_count: Arc<AtomicUsize>,
+}
+
+impl WorkerStats {
+    pub(crate) fn new(counters: Arc<Counters>, job_count: Arc<AtomicUsize>) -> Self {
+        Self {
+            counters,
+            job_count,
+        }
+    }
+
+    /// The number of jobs that were activated.
+    pub fn jobs_activated(&self) -> usize {
+        self.counters.activated.load(Ordering::SeqCst)
+    }
+
+    /// The number of jobs whose handler completed them.
+    pub fn jobs_completed(&self) -> usize {
+        self.counters.completed.load(Ordering::SeqCst)
+    }
+
+    /// The number of jobs whose handler failed them or panicked.
+    pub fn jobs_failed(&self) -> usize {
+        self.counters.failed.load(Ordering::SeqCst)
+    }
+
+    /// See `JobWorker::jobs_in_flight`.
+    pub fn jobs_in_flight(&self) -> usize {
+        self.job_count.load(Ordering::SeqCst)
+    }
+
+    /// When the last activation finished without an error, whether it returned jobs or not.
+    /// `None` until the first one does.
+    pub fn last_successful_poll(&self) -> Option<SystemTime> {
+        *self.counters.last_successful_poll.lock().unwrap()
+    }
+}
+
+impl std::fmt::Debug for WorkerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerStats")
+            .field("jobs_activated", &self.jobs_activated())
+            .field("jobs_completed", &self.jobs_completed())
+            .field("jobs_failed", &self.jobs_failed())
+            .field("jobs_in_flight", &self.jobs_in_flight())
+            .field("last_successful_poll", &self.last_successful_poll())
+            .finish()
+    }
+}
+
+/// The counters behind `WorkerStats`.
+#[derive(Default)]
+pub(crate) struct Counters {
+    activated: AtomicUsize,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    last_successful_poll: Mutex<Option<SystemTime>>,
+}
+
+impl Counters {
+    pub fn jobs_activated(&self, count: usize) {
+        self.activated.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Count the outcome of a job handler; `None` means the handler panicked.
+    pub fn job_handled(&self, job_result: Option<&JobResult>) {
+        match job_result {
+            Some(JobResult::Complete { .. }) => self.completed.fetch_add(1, Ordering::SeqCst),
+            Some(JobResult::Fail { .. }) | None => self.failed.fetch_add(1, Ordering::SeqCst),
+            Some(JobResult::NoAction) => 0,
+        };
+    }
+
+    /// Record the end of an activation; `failed` is set if the activation stream had an error.
+    pub fn activation_finished(&self, failed: &AtomicBool) {
+        if !failed.load(Ordering::SeqCst) {
+            *self.last_successful_poll.lock().unwrap() = Some(SystemTime::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::MockClient;
+    use crate::{JobResult, JobWorker};
+    use futures::FutureExt;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn counts_the_outcomes_of_jobs() {
+        let client = MockClient::new();
+        for key in 1..4 {
+            client
+                .add_job(key, "payment-service", &BTreeMap::<String, i32>::new())
+                .unwrap();
+        }
+        let worker = JobWorker::builder(client, "payment-service").build(|job| {
+            let job_result = if job.key == 2 {
+                JobResult::fail("declined")
+            } else {
+                JobResult::Complete { variables: None }
+            };
+            futures::future::ready(job_result).boxed()
+        });
+        let stats = worker.stats();
+        assert_eq!(stats.last_successful_poll(), None);
+        futures::executor::block_on(worker.activate_and_process_jobs());
+
+        assert_eq!(stats.jobs_activated(), 3);
+        assert_eq!(stats.jobs_completed(), 2);
+        assert_eq!(stats.jobs_failed(), 1);
+        assert_eq!(stats.jobs_in_flight(), 0);
+        assert!(stats.last_successful_poll().is_some());
+    }
+}
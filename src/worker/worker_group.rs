@@ -0,0 +1,225 @@
+use crate::worker::polling::{saturating_add, saturating_mul, StartDelay};
+use crate::{JobWorker, JobWorkerBuilder, PanicOption, WorkerHandle, ZeebeGateway};
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Runs several job workers that share one client. Configure a worker for each job type and call
+/// `run` to poll all of them concurrently; stopping the returned `WorkerHandle` stops every worker.
+///
+/// ```rust,ignore
+/// let (handle, run) = WorkerGroup::new(client)
+///     .job("initiate-payment", |worker| worker.max_jobs(4).build(payment_handler))
+///     .job("ship-with-insurance", |worker| {
+///         worker
+///             .timeout(Duration::from_secs(3))
+///             .max_jobs_per_second(10)
+///             .build(shipping_handler)
+///     })
+///     .run();
+/// ```
+///
+/// Use `stagger` and `start_jitter` to spread the first polls of the workers over time.
+pub struct WorkerGroup {
+    client: Arc<dyn ZeebeGateway + Send + Sync>,
+    worker_name: String,
+    poll_interval: Duration,
+    panic_option: PanicOption,
+    max_idle_backoff: Option<Duration>,
+    stagger: Duration,
+    start_jitter: Duration,
+    workers: Vec<JobWorker>,
+}
+
+impl WorkerGroup {
+    pub fn new<G: ZeebeGateway + Send + Sync + 'static>(client: G) -> Self {
+        Self {
+            client: Arc::new(client),
+            worker_name: "zeebest".to_string(),
+            poll_interval: Duration::from_secs(1),
+            panic_option: PanicOption::FailJobOnPanic,
+            max_idle_backoff: None,
+            stagger: Duration::from_secs(0),
+            start_jitter: Duration::from_secs(0),
+            workers: Vec::new(),
+        }
+    }
+
+    /// The worker name of the workers configured with `job`. Defaults to `zeebest`.
+    pub fn worker_name<S: Into<String>>(mut self, worker_name: S) -> Self {
+        self.worker_name = worker_name.into();
+        self
+    }
+
+    /// The poll interval of every worker in the group. Defaults to one second.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// What to do when the handler of a worker configured with `job` panics. Defaults to failing
+    /// the job.
+    pub fn panic_option(mut self, panic_option: PanicOption) -> Self {
+        self.panic_option = panic_option;
+        self
+    }
+
+    /// Cap the idle backoff of the workers configured with `job`, see
+    /// `JobWorkerBuilder::max_idle_backoff`.
+    pub fn max_idle_backoff(mut self, max_idle_backoff: Duration) -> Self {
+        self.max_idle_backoff = Some(max_idle_backoff);
+        self
+    }
+
+    /// Stagger the first polls of the workers: the n-th registered worker starts polling
+    /// `n * stagger` after the first one, so the workers of the group do not poll in lockstep.
+    pub fn stagger(mut self, stagger: Duration) -> Self {
+        self.stagger = stagger;
+        self
+    }
+
+    /// Delay the first poll of the whole group by a random delay of up to `start_jitter`, on top
+    /// of the stagger and of the start delays of the workers, so that many processes started at
+    /// the same time spread their activations.
+    pub fn start_jitter(mut self, start_jitter: Duration) -> Self {
+        self.start_jitter = start_jitter;
+        self
+    }
+
+    /// Register a worker for `job_type`. `configure` receives a `JobWorkerBuilder` on the client
+    /// of the group, with the worker name, panic option and idle backoff cap of the group, and
+    /// builds the worker with any other settings and its handler.
+    pub fn job<S, F>(mut self, job_type: S, configure: F) -> Self
+    where
+        S: Into<String>,
+        F: FnOnce(JobWorkerBuilder) -> JobWorker,
+    {
+        let builder = JobWorkerBuilder::with_client(self.client.clone(), job_type)
+            .worker_name(self.worker_name.clone())
+            .panic_option(self.panic_option);
+        let builder = match self.max_idle_backoff {
+            Some(max_idle_backoff) => builder.max_idle_backoff(max_idle_backoff),
+            None => builder,
+        };
+        self.workers.push(configure(builder));
+        self
+    }
+
+    /// Register a worker that was configured separately.
+    pub fn worker(mut self, worker: JobWorker) -> Self {
+        self.workers.push(worker);
+        self
+    }
+
+    /// Run all workers. The returned future resolves once the `WorkerHandle` is stopped, or once
+    /// every worker is drained after a shutdown.
+    pub fn run(self) -> (WorkerHandle, Pin<Box<dyn Future<Output = ()> + Send>>) {
+        let worker_handle = WorkerHandle::new();
+        let drained = worker_handle.drained.clone();
+        let poll_interval = self.poll_interval;
+        let start_offsets = self.start_offsets();
+        let runs: Vec<_> = self
+            .workers
+            .into_iter()
+            .zip(start_offsets)
+            .map(|(worker, start_offset)| {
+                worker.run_until(poll_interval, start_offset, &worker_handle)
+            })
+            .collect();
+        let run = futures::future::join_all(runs)
+            .map(move |_| drained.raise())
+            .boxed();
+        (worker_handle, run)
+    }
+
+    /// The delay before the first poll of every worker, on top of its own start delay.
+    fn start_offsets(&self) -> Vec<Duration> {
+        let start_jitter = StartDelay {
+            offset: Duration::from_secs(0),
+            jitter: self.start_jitter,
+        }
+        .next_delay();
+        (0..self.workers.len() as u32)
+            .map(|n| saturating_add(start_jitter, saturating_mul(self.stagger, n)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock_gateway::MockGateway;
+    use crate::worker::test::{complete, wait_until};
+    use crate::{PanicOption, WorkerGroup};
+    use futures::FutureExt;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_all_workers_until_stopped() {
+        let gateway = MockGateway::with_jobs(6);
+        let (worker_handle, run) = WorkerGroup::new(gateway.client())
+            .poll_interval(Duration::from_millis(1))
+            .worker_name("rusty-worker")
+            .job("payment-service", |worker| {
+                worker.max_jobs(2).build(complete)
+            })
+            .job("shipping-service", |worker| {
+                worker
+                    .max_jobs(2)
+                    .fetch_variables(vec!["orderId"])
+                    .build(complete)
+            })
+            .run();
+        let stopper = gateway.clone();
+        let wait_for_jobs = wait_until(move || stopper.completed_keys().len() == 6)
+            .map(move |_| worker_handle.stop());
+        futures::executor::block_on(futures::future::join(run, wait_for_jobs));
+        let requests = gateway.activate_requests.lock().unwrap();
+        let shipping = requests
+            .iter()
+            .find(|r| r.field_type == "shipping-service")
+            .unwrap();
+        assert_eq!(&shipping.fetchVariable[..], &["orderId"]);
+        assert!(requests.iter().any(|r| r.field_type == "payment-service"));
+        assert!(requests.iter().all(|r| r.worker == "rusty-worker"));
+        assert_eq!(gateway.completed_keys().len(), 6);
+    }
+
+    #[test]
+    fn staggers_the_first_polls_of_the_workers() {
+        let client = crate::testing::MockClient::new();
+        let group = WorkerGroup::new(client)
+            .stagger(Duration::from_millis(50))
+            .job("payment-service", |worker| worker.build(complete))
+            .job("shipping-service", |worker| worker.build(complete))
+            .job("billing-service", |worker| worker.build(complete));
+        let offsets: Vec<u128> = group
+            .start_offsets()
+            .iter()
+            .map(|o| o.as_millis())
+            .collect();
+        assert_eq!(offsets, vec![0, 50, 100]);
+
+        let huge = Duration::new(u64::max_value(), 0);
+        let group = group.stagger(huge).start_jitter(huge);
+        assert!(group.start_offsets().iter().skip(1).all(|&o| o >= huge));
+    }
+
+    #[test]
+    fn configures_workers_with_the_settings_of_the_group() {
+        let group = WorkerGroup::new(crate::testing::MockClient::new())
+            .worker_name("rusty-worker")
+            .panic_option(PanicOption::RetryJobOnPanic)
+            .job("payment-service", |worker| {
+                worker
+                    .timeout(Duration::new(u64::max_value(), 0))
+                    .max_jobs_per_second(5)
+                    .build(complete)
+            });
+        let job_internal = &group.workers[0].job_internal;
+        assert_eq!(job_internal.worker_name, "rusty-worker");
+        assert_eq!(job_internal.panic_option, PanicOption::RetryJobOnPanic);
+        assert_eq!(job_internal.timeout, i64::max_value());
+        assert!(job_internal.rate_limiter.is_some());
+    }
+}